@@ -1,4 +1,4 @@
-use std::fs;
+use std::{env, fs};
 
 #[allow(dead_code)] // WHYYYY is this necessary?
 fn main() {
@@ -6,6 +6,18 @@ fn main() {
 
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
+
+    // `cargo run -- render` additionally dumps the forest as text and images
+    if env::args().nth(1).as_deref() == Some("render") {
+        let grid = input_to_grid(&input);
+        let sight = compute_sight(&grid);
+
+        println!("{}", visibility_ascii(&grid, &sight));
+        println!("{}", heatmap_ascii(&sight.scores));
+
+        fs::write("visibility.ppm", visibility_ppm(&grid, &sight)).unwrap();
+        fs::write("scenic.pgm", heatmap_pgm(&sight.scores)).unwrap();
+    }
 }
 
 fn part1(input: &str) -> i32 {
@@ -34,83 +46,305 @@ fn input_to_grid(input: &str) -> Vec<Vec<u32>> {
     return grid;
 }
 
-fn is_visible(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> bool {
-    let height = grid[y][x];
-
-    return (0..x).all(|x| grid[y][x] < height)
-        || (x + 1..grid.len()).all(|x| grid[y][x] < height)
-        || (0..y).all(|y| grid[y][x] < height)
-        || (y + 1..grid.len()).all(|y| grid[y][x] < height);
+// Visibility mask and scenic scores for every tree of a (possibly
+// rectangular) grid.
+//
+// Each row and column is swept once in both directions with a monotonic
+// stack of strictly decreasing heights, which gives the viewing distance
+// and the "seen from the edge" flag of every tree in O(width * height).
+struct Sight {
+    visible: Vec<Vec<bool>>,
+    scores: Vec<Vec<u32>>,
 }
 
-fn count_visible(grid: &Vec<Vec<u32>>) -> i32 {
-    let mut count = 0;
-    for x in 0..grid.len() {
-        for y in 0..grid.len() {
-            if is_visible(grid, x, y) {
-                count += 1;
+fn look_along(grid: &[Vec<u32>], line: &[(usize, usize)], sight: &mut Sight) {
+    let mut stack: Vec<usize> = Vec::with_capacity(line.len());
+
+    for (id, &(x, y)) in line.iter().enumerate() {
+        let height = grid[y][x];
+
+        while let Some(&top) = stack.last() {
+            let (top_x, top_y) = line[top];
+            if grid[top_y][top_x] >= height {
+                break;
             }
+            stack.pop();
         }
+
+        let distance = match stack.last() {
+            Some(&top) => id - top,
+            None => {
+                sight.visible[y][x] = true;
+                id
+            }
+        };
+        sight.scores[y][x] *= distance as u32;
+
+        stack.push(id);
     }
-    return count;
 }
 
-fn score_meta(line: Vec<u32>) -> u32 {
-    assert!(!line.is_empty());
+fn compute_sight(grid: &[Vec<u32>]) -> Sight {
+    let height = grid.len();
+    let width = grid.first().map_or(0, |row| row.len());
 
-    let ref_height = line.first().unwrap();
-    let mut score = 0;
+    let mut sight = Sight {
+        visible: vec![vec![false; width]; height],
+        scores: vec![vec![1; width]; height],
+    };
 
-    for (id, height) in line.iter().enumerate() {
-        if id == 0 {
-            continue;
-        }
+    for y in 0..height {
+        let mut line: Vec<(usize, usize)> = (0..width).map(|x| (x, y)).collect();
+        look_along(grid, &line, &mut sight);
+        line.reverse();
+        look_along(grid, &line, &mut sight);
+    }
+
+    for x in 0..width {
+        let mut line: Vec<(usize, usize)> = (0..height).map(|y| (x, y)).collect();
+        look_along(grid, &line, &mut sight);
+        line.reverse();
+        look_along(grid, &line, &mut sight);
+    }
 
+    sight
+}
+
+fn visibility_mask(grid: &[Vec<u32>]) -> Vec<Vec<bool>> {
+    compute_sight(grid).visible
+}
+
+fn scenic_scores(grid: &[Vec<u32>]) -> Vec<Vec<u32>> {
+    compute_sight(grid).scores
+}
+
+fn count_visible(grid: &[Vec<u32>]) -> i32 {
+    visibility_mask(grid)
+        .iter()
+        .flatten()
+        .filter(|&&visible| visible)
+        .count() as i32
+}
+
+fn highest_scenic_score(grid: &[Vec<u32>]) -> u32 {
+    max_score(&scenic_scores(grid))
+}
+
+fn max_score(scores: &[Vec<u32>]) -> u32 {
+    scores.iter().flatten().copied().max().unwrap_or(0)
+}
+
+// Naive per-tree versions. The `score_*` functions are still used to find the
+// trees seen from a single spot, the rest only cross-checks the sweeps above.
+
+#[cfg(test)]
+fn is_visible(grid: &[Vec<u32>], x: usize, y: usize) -> bool {
+    let height = grid[y][x];
+    let width = grid[y].len();
+
+    (0..x).all(|x| grid[y][x] < height)
+        || (x + 1..width).all(|x| grid[y][x] < height)
+        || (0..y).all(|y| grid[y][x] < height)
+        || (y + 1..grid.len()).all(|y| grid[y][x] < height)
+}
+
+fn score_meta(ref_height: u32, heights: impl Iterator<Item = u32>) -> u32 {
+    let mut score = 0;
+
+    for height in heights {
         score += 1;
         if height >= ref_height {
             break;
         }
     }
 
-    return score;
+    score
 }
 
-fn score_left(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> u32 {
-    return score_meta((0..=x).rev().map(|x| grid[y][x]).collect());
+fn score_left(grid: &[Vec<u32>], x: usize, y: usize) -> u32 {
+    score_meta(grid[y][x], (0..x).rev().map(|x| grid[y][x]))
 }
 
-fn score_up(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> u32 {
-    return score_meta((0..=y).rev().map(|y| grid[y][x]).collect());
+fn score_up(grid: &[Vec<u32>], x: usize, y: usize) -> u32 {
+    score_meta(grid[y][x], (0..y).rev().map(|y| grid[y][x]))
 }
 
-fn score_right(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> u32 {
-    return score_meta((x..grid.len()).map(|x| grid[y][x]).collect());
+fn score_right(grid: &[Vec<u32>], x: usize, y: usize) -> u32 {
+    score_meta(grid[y][x], (x + 1..grid[y].len()).map(|x| grid[y][x]))
 }
 
-fn score_down(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> u32 {
-    return score_meta((y..grid.len()).map(|y| grid[y][x]).collect());
+fn score_down(grid: &[Vec<u32>], x: usize, y: usize) -> u32 {
+    score_meta(grid[y][x], (y + 1..grid.len()).map(|y| grid[y][x]))
 }
 
-fn scenic_score(grid: &Vec<Vec<u32>>, x: usize, y: usize) -> u32 {
-    return score_up(grid, x, y)
-        * score_down(grid, x, y)
-        * score_left(grid, x, y)
-        * score_right(grid, x, y);
+#[cfg(test)]
+fn scenic_score(grid: &[Vec<u32>], x: usize, y: usize) -> u32 {
+    score_up(grid, x, y) * score_down(grid, x, y) * score_left(grid, x, y) * score_right(grid, x, y)
 }
 
-fn highest_scenic_score(grid: &Vec<Vec<u32>>) -> u32 {
-    let mut highest_score = 0;
-
-    for x in 0..grid.len() {
-        for y in 0..grid.len() {
-            let score = scenic_score(grid, x, y);
-            if score > highest_score {
-                highest_score = score;
+// Position of the tree with the highest of the scenic `scores` (first one in
+// reading order on ties), or `None` for an empty grid.
+fn best_scenic_spot(scores: &[Vec<u32>]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    for (y, row) in scores.iter().enumerate() {
+        for (x, &score) in row.iter().enumerate() {
+            if best.is_none_or(|(bx, by)| score > scores[by][bx]) {
+                best = Some((x, y));
             }
         }
     }
 
-    return highest_score;
+    best
+}
+
+// Mask of the trees that can be seen from the tree house at `(x, y)`,
+// i.e. everything within its viewing distance in the four directions.
+fn seen_from(grid: &[Vec<u32>], x: usize, y: usize) -> Vec<Vec<bool>> {
+    let mut seen: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+
+    for d in 1..=score_left(grid, x, y) as usize {
+        seen[y][x - d] = true;
+    }
+    for d in 1..=score_right(grid, x, y) as usize {
+        seen[y][x + d] = true;
+    }
+    for d in 1..=score_up(grid, x, y) as usize {
+        seen[y - d][x] = true;
+    }
+    for d in 1..=score_down(grid, x, y) as usize {
+        seen[y + d][x] = true;
+    }
+
+    seen
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum TreeKind {
+    Hidden,
+    Visible,
+    SeenFromBest,
+    Best,
+}
+
+fn classify_trees(grid: &[Vec<u32>], sight: &Sight) -> Vec<Vec<TreeKind>> {
+    let best = best_scenic_spot(&sight.scores);
+    let seen = best.map(|(x, y)| seen_from(grid, x, y));
+
+    sight
+        .visible
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(x, &visible)| {
+                    if best == Some((x, y)) {
+                        TreeKind::Best
+                    } else if seen.as_ref().is_some_and(|seen| seen[y][x]) {
+                        TreeKind::SeenFromBest
+                    } else if visible {
+                        TreeKind::Visible
+                    } else {
+                        TreeKind::Hidden
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Visibility map: `#` is visible from outside the forest, `.` is hidden,
+// `X` is the best scenic spot and `o` the trees seen from it.
+fn visibility_ascii(grid: &[Vec<u32>], sight: &Sight) -> String {
+    classify_trees(grid, sight)
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|kind| match kind {
+                    TreeKind::Hidden => '.',
+                    TreeKind::Visible => '#',
+                    TreeKind::SeenFromBest => 'o',
+                    TreeKind::Best => 'X',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+const HEAT_RAMP: &[u8] = b" .:-=+*#%@";
+
+fn heat_level(score: u32, max_score: u32, levels: usize) -> usize {
+    if max_score == 0 {
+        return 0;
+    }
+    score as usize * (levels - 1) / max_score as usize
+}
+
+// Scenic scores as an ASCII heatmap, scaled to the highest score. The best
+// scenic spot is marked with `X`.
+fn heatmap_ascii(scores: &[Vec<u32>]) -> String {
+    let max_score = max_score(scores);
+    let best = best_scenic_spot(scores);
+
+    scores
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(x, &score)| {
+                    if best == Some((x, y)) {
+                        'X'
+                    } else {
+                        HEAT_RAMP[heat_level(score, max_score, HEAT_RAMP.len())] as char
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Scenic scores as a plain (P2) grayscale PGM image, scaled to 0..=255.
+fn heatmap_pgm(scores: &[Vec<u32>]) -> String {
+    let max_score = max_score(scores);
+    let width = scores.first().map_or(0, |row| row.len());
+
+    let mut image = format!("P2\n{} {}\n255\n", width, scores.len());
+    for row in scores {
+        let line: Vec<String> = row
+            .iter()
+            .map(|&score| heat_level(score, max_score, 256).to_string())
+            .collect();
+        image.push_str(&line.join(" "));
+        image.push('\n');
+    }
+
+    image
+}
+
+// The visibility map of `visibility_ascii` as a plain (P3) PPM image.
+fn visibility_ppm(grid: &[Vec<u32>], sight: &Sight) -> String {
+    let kinds = classify_trees(grid, sight);
+    let width = kinds.first().map_or(0, |row| row.len());
+
+    let mut image = format!("P3\n{} {}\n255\n", width, kinds.len());
+    for row in &kinds {
+        let line: Vec<&str> = row
+            .iter()
+            .map(|kind| match kind {
+                TreeKind::Hidden => "20 60 20",
+                TreeKind::Visible => "60 180 60",
+                TreeKind::SeenFromBest => "240 220 60",
+                TreeKind::Best => "230 40 40",
+            })
+            .collect();
+        image.push_str(&line.join(" "));
+        image.push('\n');
+    }
+
+    image
 }
 
 #[cfg(test)]
@@ -167,4 +401,65 @@ mod tests {
 
         assert_eq!(highest_scenic_score(&grid), 8);
     }
+
+    #[test]
+    fn test_rectangular() {
+        let input = "3037312
+2551209
+6533281
+3354904
+3539017";
+
+        let grid = input_to_grid(input);
+        let mask = visibility_mask(&grid);
+        let scores = scenic_scores(&grid);
+
+        for y in 0..grid.len() {
+            for x in 0..grid[y].len() {
+                assert_eq!(mask[y][x], is_visible(&grid, x, y), "({x}, {y})");
+                assert_eq!(scores[y][x], scenic_score(&grid, x, y), "({x}, {y})");
+            }
+        }
+
+        let naive_count = (0..grid.len())
+            .flat_map(|y| (0..grid[y].len()).map(move |x| (x, y)))
+            .filter(|&(x, y)| is_visible(&grid, x, y))
+            .count() as i32;
+        assert_eq!(count_visible(&grid), naive_count);
+    }
+
+    #[test]
+    fn test_render() {
+        let input = "30373
+25512
+65332
+33549
+35390";
+
+        let grid = input_to_grid(input);
+        let sight = compute_sight(&grid);
+
+        assert_eq!(best_scenic_spot(&sight.scores), Some((2, 3)));
+        assert_eq!(
+            visibility_ascii(&grid, &sight),
+            "#####
+##o.#
+##o##
+ooXoo
+##o##"
+        );
+        assert_eq!(
+            heatmap_ascii(&sight.scores),
+            "     \n .=. \n *.: \n .X- \n     "
+        );
+
+        let pgm = heatmap_pgm(&sight.scores);
+        assert!(pgm.starts_with("P2\n5 5\n255\n"));
+        assert_eq!(pgm.lines().count(), 3 + 5);
+
+        let ppm = visibility_ppm(&grid, &sight);
+        assert!(ppm.starts_with("P3\n5 5\n255\n"));
+        assert_eq!(ppm.lines().nth(6).unwrap().split(' ').count(), 3 * 5);
+        assert!(ppm.lines().nth(6).unwrap().contains("230 40 40"));
+    }
 }
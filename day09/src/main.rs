@@ -1,11 +1,52 @@
 use itertools::Itertools;
-use std::{collections::HashSet, fs};
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, Write},
+    str::FromStr,
+};
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
 
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
+
+    let args: Vec<String> = env::args().collect();
+
+    // `cargo run -- trails` summarizes the trail of every knot,
+    // `cargo run -- replay <movements> [knots]` prints every step of a motion list,
+    // `cargo run -- replay-ppm <movements> <directory> [knots]` writes them as PPM frames
+    match args.get(1).map(String::as_str) {
+        Some("trails") => {
+            let mut grid = Grid::new(9);
+            grid.move_list(&input);
+
+            for knot in 0..grid.rope.len() {
+                let (last_step, last_point) = grid.first_visits(knot).last().unwrap();
+                println!(
+                    "Knot {}: {} cells, last new cell ({}, {}) at step {}",
+                    knot,
+                    grid.count_knot(knot),
+                    last_point.x,
+                    last_point.y,
+                    last_step
+                );
+            }
+        }
+        Some("replay") => {
+            let list = fs::read_to_string(&args[2]).unwrap();
+            let knots = args.get(3).map_or(9, |knots| knots.parse().unwrap());
+            write_text_replay(&list, knots, &mut io::stdout()).unwrap();
+        }
+        Some("replay-ppm") => {
+            let list = fs::read_to_string(&args[2]).unwrap();
+            let knots = args.get(4).map_or(9, |knots| knots.parse().unwrap());
+            let frames = write_ppm_replay(&list, knots, &args[3], 8).unwrap();
+            println!("Wrote {} frames to {}", frames, args[3]);
+        }
+        _ => {}
+    }
 }
 
 fn part1(input: &str) -> i32 {
@@ -23,32 +64,97 @@ struct Point {
     y: i32,
 }
 
-type Movement = (char, i32);
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Direction {
+    U,
+    D,
+    L,
+    R,
+    UL,
+    UR,
+    DL,
+    DR,
+}
+
+impl Direction {
+    fn delta(self) -> Point {
+        let (x, y) = match self {
+            Direction::U => (0, 1),
+            Direction::D => (0, -1),
+            Direction::L => (-1, 0),
+            Direction::R => (1, 0),
+            Direction::UL => (-1, 1),
+            Direction::UR => (1, 1),
+            Direction::DL => (-1, -1),
+            Direction::DR => (1, -1),
+        };
+        Point { x, y }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "U" => Ok(Direction::U),
+            "D" => Ok(Direction::D),
+            "L" => Ok(Direction::L),
+            "R" => Ok(Direction::R),
+            "UL" => Ok(Direction::UL),
+            "UR" => Ok(Direction::UR),
+            "DL" => Ok(Direction::DL),
+            "DR" => Ok(Direction::DR),
+            _ => Err(format!("Invalid direction: {}", s)),
+        }
+    }
+}
+
+type Movement = (Direction, i32);
 
 // a set of visited points, as a rust typedef
 type Visited = HashSet<Point>;
 
-struct Grid {
+// every cell a single knot has been in, plus the step at which it got there first
+#[derive(Debug, Default)]
+struct Trail {
     visited: Visited,
-    head_visited: Visited,
+    first_visits: Vec<(usize, Point)>,
+}
+
+impl Trail {
+    fn visit(&mut self, step: usize, point: Point) {
+        if self.visited.insert(point) {
+            self.first_visits.push((step, point));
+        }
+    }
+}
+
+struct Grid {
+    trails: Vec<Trail>,
     rope: Vec<Point>,
+    // maximum (chebyshev) distance between two knots before the trailing one moves
+    slack: i32,
+    steps: usize,
     print_boundary: Boundary,
 }
 
 impl Grid {
     fn new(length: usize) -> Grid {
-        let mut grid = Grid {
-            visited: Visited::new(),
-            head_visited: Visited::new(),
-            rope: Vec::new(),
-            print_boundary: Boundary::new(),
-        };
+        Grid::with_slack(length, 1)
+    }
 
+    fn with_slack(length: usize, slack: i32) -> Grid {
         assert!(length > 0);
+        assert!(slack > 0);
 
-        for _ in 0..=length {
-            grid.rope.push(Point { x: 0, y: 0 });
-        }
+        let mut grid = Grid {
+            trails: (0..=length).map(|_| Trail::default()).collect(),
+            rope: vec![Point { x: 0, y: 0 }; length + 1],
+            slack,
+            steps: 0,
+            print_boundary: Boundary::new(),
+        };
 
         grid.visit();
 
@@ -56,87 +162,61 @@ impl Grid {
     }
 
     fn visit(&mut self) {
-        for point in self.rope.iter() {
+        for (point, trail) in self.rope.iter().zip(self.trails.iter_mut()) {
             self.print_boundary.add(point);
+            trail.visit(self.steps, *point);
         }
-        self.head_visited.insert(self.rope.first().unwrap().clone());
-        self.visited.insert(self.rope.last().unwrap().clone());
     }
 
-    fn move_single(&mut self, direction: char) {
+    fn move_single(&mut self, direction: Direction) {
+        let delta = direction.delta();
         let head = self.rope.first_mut().unwrap();
-        match direction {
-            'U' => head.y += 1,
-            'D' => head.y -= 1,
-            'R' => head.x += 1,
-            'L' => head.x -= 1,
-            _ => panic!("Invalid direction: {}", direction),
-        };
+        head.x += delta.x;
+        head.y += delta.y;
 
         self.fix_rope();
 
+        self.steps += 1;
         self.visit();
     }
 
-    fn fix_rope_pair(leading: &Point, trailing: &mut Point) {
-        let dx = leading.x - trailing.x;
-        let dy = leading.y - trailing.y;
-
-        if dx > 1 {
-            trailing.x = leading.x - 1;
-            if dy == dx {
-                trailing.y = leading.y - 1;
-            } else if dy == -dx {
-                trailing.y = leading.y + 1;
-            } else {
-                trailing.y = leading.y;
-            }
-        } else if dx < -1 {
-            trailing.x = leading.x + 1;
-            if dy == dx {
-                trailing.y = leading.y + 1;
-            } else if dy == -dx {
-                trailing.y = leading.y - 1;
-            } else {
-                trailing.y = leading.y;
-            }
-        } else if dy > 1 {
-            trailing.y = leading.y - 1;
-            if dx == dy {
-                trailing.x = leading.x - 1;
-            } else if dx == -dy {
-                trailing.x = leading.x + 1;
-            } else {
-                trailing.x = leading.x;
-            }
-        } else if dy < -1 {
-            trailing.y = leading.y + 1;
-            if dx == dy {
-                trailing.x = leading.x + 1;
-            } else if dx == -dy {
-                trailing.x = leading.x - 1;
-            } else {
-                trailing.x = leading.x;
-            }
+    fn fix_rope_pair(leading: &Point, trailing: &mut Point, slack: i32) {
+        while (leading.x - trailing.x)
+            .abs()
+            .max((leading.y - trailing.y).abs())
+            > slack
+        {
+            trailing.x += (leading.x - trailing.x).signum();
+            trailing.y += (leading.y - trailing.y).signum();
         }
     }
 
     fn fix_rope(&mut self) {
         for i in 0..self.rope.len() - 1 {
             let leading = self.rope[i];
-            let mut trailing = self.rope.get_mut(i + 1).unwrap();
-            Grid::fix_rope_pair(&leading, &mut trailing)
+            let trailing = self.rope.get_mut(i + 1).unwrap();
+            Grid::fix_rope_pair(&leading, trailing, self.slack)
         }
     }
 
-    fn move_amount(&mut self, direction: char, amount: i32) {
+    fn move_amount(&mut self, direction: Direction, amount: i32) {
         for _ in 0..amount {
             self.move_single(direction);
         }
     }
 
     fn count(&self) -> usize {
-        self.visited.len()
+        self.count_knot(self.rope.len() - 1)
+    }
+
+    // number of distinct cells visited by a knot (0 is the head)
+    fn count_knot(&self, knot: usize) -> usize {
+        self.trails[knot].visited.len()
+    }
+
+    // (step, position) of every cell the knot entered for the first time, in order
+    fn first_visits(&self, knot: usize) -> &[(usize, Point)] {
+        &self.trails[knot].first_visits
     }
 
     fn split_movements(list: &str) -> Vec<Movement> {
@@ -146,7 +226,7 @@ impl Grid {
                 let (direction, amount) = line.split(' ').next_tuple().unwrap();
 
                 (
-                    direction.chars().next().unwrap(),
+                    direction.parse::<Direction>().unwrap(),
                     amount.parse::<i32>().unwrap(),
                 )
             })
@@ -162,45 +242,142 @@ impl Grid {
     }
 }
 
-impl std::fmt::Display for Grid {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        // 2D grid of '.' with size of the boundary
-        let mut grid =
-            vec![
-                vec!['.'; (self.print_boundary.max.y - self.print_boundary.min.y + 2) as usize];
-                (self.print_boundary.max.x - self.print_boundary.min.x + 1) as usize
-            ];
-
-        let off = Point {
-            x: -self.print_boundary.min.x,
-            y: -self.print_boundary.min.y,
+impl Grid {
+    // the print boundary as rows of chars, top row first: '.' for empty cells,
+    // 's' for the start and 'H', '1', '2', ... for the knots
+    fn cells(&self) -> Vec<Vec<char>> {
+        let min = self.print_boundary.min;
+        let max = self.print_boundary.max;
+
+        let mut cells = vec![vec!['.'; (max.x - min.x + 1) as usize]; (max.y - min.y + 1) as usize];
+
+        let mut mark = |point: &Point, c: char| {
+            cells[(max.y - point.y) as usize][(point.x - min.x) as usize] = c;
         };
 
-        // mark (0,0) with 's', mind the min_x and min_y
-        grid[0 + off.y as usize][0 + off.x as usize] = 's';
+        mark(&Point { x: 0, y: 0 }, 's');
 
-        // mark the visited points with 'H', '1', '2', ...
+        // leading knots are drawn last so they cover the ones behind them
         for (i, point) in self.rope.iter().enumerate().rev() {
             let c = match i {
                 0 => 'H',
-                1..=9 => (i as u8 + b'0') as char,
+                1..=9 => (b'0' + i as u8) as char,
                 _ => '#',
             };
-            grid[(point.y + off.y) as usize][(point.x + off.x) as usize] = c;
+            mark(point, c);
         }
 
-        writeln!(f, "").unwrap();
-        for row in grid.iter().rev() {
-            for cell in row {
-                write!(f, "{}", cell).unwrap();
+        cells
+    }
+
+    // plain (P3) PPM image of `cells`, with every cell drawn as a `scale` x `scale` square
+    fn to_ppm(&self, scale: usize) -> String {
+        let cells = self.cells();
+        let width = cells[0].len() * scale;
+        let height = cells.len() * scale;
+
+        let knots = self.rope.len();
+        let color = |c: char| match c {
+            '.' => (24, 24, 24),
+            's' => (60, 90, 220),
+            'H' => (230, 40, 40),
+            '#' => (240, 230, 80),
+            digit => {
+                let i = digit.to_digit(10).unwrap() as usize;
+                (240, 120 + 110 * i / knots, 40)
+            }
+        };
+
+        let mut image = format!("P3\n{} {}\n255\n", width, height);
+        for row in &cells {
+            let line: Vec<String> = row
+                .iter()
+                .flat_map(|&c| std::iter::repeat_n(color(c), scale))
+                .map(|(r, g, b)| format!("{} {} {}", r, g, b))
+                .collect();
+            let line = line.join(" ");
+
+            for _ in 0..scale {
+                image.push_str(&line);
+                image.push('\n');
             }
-            writeln!(f, "").unwrap();
+        }
+
+        image
+    }
+}
+
+impl std::fmt::Display for Grid {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for row in self.cells() {
+            writeln!(f, "{}", row.iter().collect::<String>())?;
         }
 
         Ok(())
     }
 }
 
+// A fresh grid whose print boundary already covers the whole run, so that all
+// frames of a replay have the same size.
+fn replay_grid(list: &str, rope_length: usize) -> Grid {
+    let mut grid_for_boundary = Grid::new(rope_length);
+    grid_for_boundary.move_list(list);
+
+    let mut grid = Grid::new(rope_length);
+    grid.print_boundary = grid_for_boundary.print_boundary;
+
+    grid
+}
+
+// Writes every step of the movements as a text frame, in the format of the puzzle description
+fn write_text_replay(list: &str, rope_length: usize, out: &mut impl Write) -> io::Result<()> {
+    let mut grid = replay_grid(list, rope_length);
+
+    writeln!(out, "== Initial State ==\n\n{}", grid)?;
+
+    for (direction, amount) in Grid::split_movements(list) {
+        writeln!(out, "== {:?} {} ==\n", direction, amount)?;
+
+        for _ in 0..amount {
+            grid.move_single(direction);
+            writeln!(out, "{}", grid)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Writes every step of the movements as `frame_00000.ppm`, `frame_00001.ppm`, ... into
+// `directory` and returns the number of frames
+fn write_ppm_replay(
+    list: &str,
+    rope_length: usize,
+    directory: &str,
+    scale: usize,
+) -> io::Result<usize> {
+    let mut grid = replay_grid(list, rope_length);
+    let mut frame = 0;
+
+    let mut write_frame = |grid: &Grid| -> io::Result<()> {
+        fs::write(
+            format!("{}/frame_{:05}.ppm", directory, frame),
+            grid.to_ppm(scale),
+        )?;
+        frame += 1;
+        Ok(())
+    };
+
+    write_frame(&grid)?;
+    for (direction, amount) in Grid::split_movements(list) {
+        for _ in 0..amount {
+            grid.move_single(direction);
+            write_frame(&grid)?;
+        }
+    }
+
+    Ok(frame)
+}
+
 fn count_visited(list: &str, rope_length: usize) -> usize {
     let mut grid = Grid::new(rope_length);
 
@@ -240,29 +417,9 @@ impl Boundary {
 
 #[cfg(test)]
 fn debug_visited(list: &str, rope_length: usize, filename: &str) {
-    use std::io::Write;
-
-    let mut file = fs::File::create(filename).unwrap();
-
-    let mut grid_for_boundary = Grid::new(rope_length);
-    grid_for_boundary.move_list(list);
-
-    let mut grid = Grid::new(rope_length);
-    grid.print_boundary = grid_for_boundary.print_boundary;
-
-    writeln!(file, "== {} ==", "Initial State").unwrap();
-    writeln!(file, "{}", grid).unwrap();
-
-    let movements = Grid::split_movements(list);
-
-    for (direction, amount) in movements {
-        writeln!(file, "== {} {} ==", direction, amount).unwrap();
-
-        for _ in 0..amount {
-            grid.move_single(direction);
-            writeln!(file, "{}", grid).unwrap();
-        }
-    }
+    // keep the log out of the crate directory
+    let mut file = fs::File::create(env::temp_dir().join(filename)).unwrap();
+    write_text_replay(list, rope_length, &mut file).unwrap();
 }
 
 #[cfg(test)]
@@ -313,4 +470,58 @@ U 20";
 
         assert_eq!(count_visited(TEST_INPUT_2, 9), 36);
     }
+
+    #[test]
+    fn test_knot_trails() {
+        let mut grid = Grid::new(9);
+        grid.move_list(TEST_INPUT_1);
+
+        // knots never influence the knots in front of them
+        assert_eq!(grid.count_knot(1), count_visited(TEST_INPUT_1, 1));
+        assert_eq!(grid.count_knot(9), count_visited(TEST_INPUT_1, 9));
+        assert_eq!(grid.first_visits(0).len(), grid.count_knot(0));
+
+        let mut grid = Grid::new(1);
+        grid.move_list("UR 3");
+        assert_eq!(grid.count_knot(0), 4);
+        assert_eq!(
+            grid.first_visits(1),
+            &[
+                (0, Point { x: 0, y: 0 }),
+                (2, Point { x: 1, y: 1 }),
+                (3, Point { x: 2, y: 2 })
+            ]
+        );
+
+        let mut grid = Grid::with_slack(1, 2);
+        grid.move_list("R 5\nDL 1");
+        assert_eq!(grid.rope[1], Point { x: 3, y: 0 });
+        assert_eq!(grid.count(), 4);
+        assert_eq!(grid.first_visits(1)[1], (3, Point { x: 1, y: 0 }));
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut out = Vec::new();
+        write_text_replay("R 4\nU 4", 1, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let frames: Vec<&str> = out.split("\n\n").collect();
+        assert_eq!(frames[0], "== Initial State ==");
+        assert_eq!(frames[1], ".....\n.....\n.....\n.....\nH....");
+        assert_eq!(frames[2], "== R 4 ==");
+        assert_eq!(frames[3], ".....\n.....\n.....\n.....\n1H...");
+        assert_eq!(frames[6], ".....\n.....\n.....\n.....\ns..1H");
+        assert_eq!(frames[7], "== U 4 ==");
+        assert_eq!(frames[11], "....H\n....1\n.....\n.....\ns....");
+
+        // wider than tall, which used to mix up the axes
+        let mut grid = replay_grid("R 5\nU 1", 9);
+        grid.move_list("R 5\nU 1");
+        assert_eq!(grid.to_string(), ".....H\n54321.\n");
+
+        let ppm = grid.to_ppm(2);
+        assert!(ppm.starts_with("P3\n12 4\n255\n"));
+        assert_eq!(ppm.lines().count(), 3 + 4);
+    }
 }
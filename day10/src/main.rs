@@ -1,10 +1,76 @@
-use std::fs;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+};
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // `cargo run -- <trace|watch|break> <program> [cycle...]` runs a custom program instead:
+    // `trace` prints the registers during every cycle, `watch` during the given cycles and
+    // `break` stops before each of the given cycles
+    if args.len() > 2 {
+        run_custom(&args[1], &fs::read_to_string(&args[2]).unwrap(), &args[3..]);
+        return;
+    }
+
     let input = fs::read_to_string("input.txt").unwrap();
 
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
+
+    // `cargo run -- pbm` also saves the screen as an image
+    if args.get(1).map(String::as_str) == Some("pbm") {
+        let cpu = CPU::run_str(&input);
+        fs::write("screen.pbm", cpu.crt.framebuffer.to_pbm()).unwrap();
+    }
+}
+
+fn format_registers(registers: &Registers) -> String {
+    REGISTER_NAMES
+        .iter()
+        .zip(registers)
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn run_custom(mode: &str, program: &str, cycles: &[String]) {
+    let mut cpu = CPU::from_str(program);
+    let cycles = cycles.iter().map(|cycle| cycle.parse::<usize>().unwrap());
+
+    match mode {
+        "trace" => cpu.on_every_cycle(|cycle, registers| {
+            println!("During cycle {}: {}", cycle, format_registers(registers))
+        }),
+        "watch" => {
+            for cycle in cycles {
+                cpu.on_cycle(cycle, |cycle, registers| {
+                    println!("During cycle {}: {}", cycle, format_registers(registers))
+                });
+            }
+        }
+        "break" => {
+            for cycle in cycles {
+                cpu.add_breakpoint(cycle);
+            }
+        }
+        _ => panic!("Invalid mode: {}", mode),
+    }
+
+    while let Stop::Breakpoint(cycle) = cpu.run() {
+        println!(
+            "Breakpoint before cycle {}: {}",
+            cycle,
+            format_registers(&cpu.registers)
+        );
+    }
+
+    println!(
+        "Halted after {} cycles: {}",
+        cpu.cycle,
+        format_registers(&cpu.registers)
+    );
 }
 
 fn part1(input: &str) -> i32 {
@@ -12,102 +78,545 @@ fn part1(input: &str) -> i32 {
     cpu.signal_sum()
 }
 
-fn part2(input: &str) -> i32 {
+fn part2(input: &str) -> String {
     let cpu = CPU::run_str(input);
 
-    println!("{}", cpu.output);
+    let screen = cpu.crt.framebuffer.to_string();
+
+    ocr(&screen).unwrap_or_else(|err| panic!("{}\n{}", err, screen))
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+// every glyph is followed by one blank column
+const GLYPH_SPACING: usize = GLYPH_WIDTH + 1;
+
+// the 4x6 font used by the CRT, one row per line
+const FONT: [(char, &str); 18] = [
+    ('A', ".##.\n#..#\n#..#\n####\n#..#\n#..#"),
+    ('B', "###.\n#..#\n###.\n#..#\n#..#\n###."),
+    ('C', ".##.\n#..#\n#...\n#...\n#..#\n.##."),
+    ('E', "####\n#...\n###.\n#...\n#...\n####"),
+    ('F', "####\n#...\n###.\n#...\n#...\n#..."),
+    ('G', ".##.\n#..#\n#...\n#.##\n#..#\n.###"),
+    ('H', "#..#\n#..#\n####\n#..#\n#..#\n#..#"),
+    ('I', ".###\n..#.\n..#.\n..#.\n..#.\n.###"),
+    ('J', "..##\n...#\n...#\n...#\n#..#\n.##."),
+    ('K', "#..#\n#.#.\n##..\n#.#.\n#.#.\n#..#"),
+    ('L', "#...\n#...\n#...\n#...\n#...\n####"),
+    ('O', ".##.\n#..#\n#..#\n#..#\n#..#\n.##."),
+    ('P', "###.\n#..#\n#..#\n###.\n#...\n#..."),
+    ('R', "###.\n#..#\n#..#\n###.\n#.#.\n#..#"),
+    ('S', ".###\n#...\n#...\n.##.\n...#\n###."),
+    ('U', "#..#\n#..#\n#..#\n#..#\n#..#\n.##."),
+    ('Y', "#...\n#...\n.#.#\n..#.\n..#.\n..#."),
+    ('Z', "####\n...#\n..#.\n.#..\n#...\n####"),
+];
+
+// Reads the letters off a CRT screen. Lit pixels are '#', anything else is dark.
+fn ocr(screen: &str) -> Result<String, String> {
+    let rows: Vec<Vec<char>> = screen
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.chars().collect())
+        .collect();
+
+    if rows.len() != GLYPH_HEIGHT {
+        return Err(format!(
+            "Expected {} rows of pixels, got {}",
+            GLYPH_HEIGHT,
+            rows.len()
+        ));
+    }
+
+    let width = rows[0].len();
+    if rows.iter().any(|row| row.len() != width) {
+        return Err("Rows of pixels differ in length".to_string());
+    }
+
+    // the blank column after the last glyph may be cut off, but nothing else
+    let glyphs = (width + 1) / GLYPH_SPACING;
+    if !matches!(width % GLYPH_SPACING, 0 | GLYPH_WIDTH) {
+        return Err(format!(
+            "Incomplete glyph {} at columns {}..{}",
+            glyphs,
+            glyphs * GLYPH_SPACING,
+            width
+        ));
+    }
+
+    (0..glyphs)
+        .map(|glyph| {
+            let left = glyph * GLYPH_SPACING;
+            let cell = rows
+                .iter()
+                .map(|row| {
+                    row[left..left + GLYPH_WIDTH]
+                        .iter()
+                        .map(|&c| if c == '#' { '#' } else { '.' })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            FONT.iter()
+                .find(|(_, pattern)| *pattern == cell)
+                .map(|&(letter, _)| letter)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown glyph {} at columns {}..{}:\n{}",
+                        glyph,
+                        left,
+                        left + GLYPH_WIDTH,
+                        cell
+                    )
+                })
+        })
+        .collect()
+}
+
+const REGISTER_NAMES: [&str; 4] = ["x", "y", "z", "w"];
+
+type Registers = [i32; REGISTER_NAMES.len()];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandType {
+    Register,
+    // a register or an immediate number
+    Value,
+    Label,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(usize),
+    Immediate(i32),
+    // index of the instruction following the label
+    Target(usize),
+}
+
+impl Operand {
+    fn value(&self, registers: &Registers) -> i32 {
+        match *self {
+            Operand::Register(register) => registers[register],
+            Operand::Immediate(value) => value,
+            Operand::Target(target) => target as i32,
+        }
+    }
+
+    fn register(&self) -> usize {
+        match *self {
+            Operand::Register(register) => register,
+            _ => panic!("Operand is not a register: {:?}", self),
+        }
+    }
+
+    fn target(&self) -> usize {
+        match *self {
+            Operand::Target(target) => target,
+            _ => panic!("Operand is not a label: {:?}", self),
+        }
+    }
+}
+
+// Applied once the last cycle of an instruction is over. Returns the index of the
+// next instruction for jumps, `None` to continue with the following one.
+type Effect = fn(&mut Registers, &[Operand]) -> Option<usize>;
+
+struct OpcodeSpec {
+    name: &'static str,
+    operands: &'static [OperandType],
+    cycles: usize,
+    effect: Effect,
+}
+
+const INSTRUCTION_SET: &[OpcodeSpec] = &[
+    OpcodeSpec {
+        name: "noop",
+        operands: &[],
+        cycles: 1,
+        effect: |_, _| None,
+    },
+    OpcodeSpec {
+        name: "addx",
+        operands: &[OperandType::Value],
+        cycles: 2,
+        effect: |registers, operands| {
+            registers[0] += operands[0].value(registers);
+            None
+        },
+    },
+    OpcodeSpec {
+        name: "set",
+        operands: &[OperandType::Register, OperandType::Value],
+        cycles: 1,
+        effect: |registers, operands| {
+            registers[operands[0].register()] = operands[1].value(registers);
+            None
+        },
+    },
+    OpcodeSpec {
+        name: "add",
+        operands: &[OperandType::Register, OperandType::Value],
+        cycles: 2,
+        effect: |registers, operands| {
+            registers[operands[0].register()] += operands[1].value(registers);
+            None
+        },
+    },
+    OpcodeSpec {
+        name: "mul",
+        operands: &[OperandType::Register, OperandType::Value],
+        cycles: 3,
+        effect: |registers, operands| {
+            registers[operands[0].register()] *= operands[1].value(registers);
+            None
+        },
+    },
+    OpcodeSpec {
+        name: "jmp",
+        operands: &[OperandType::Label],
+        cycles: 1,
+        effect: |_, operands| Some(operands[0].target()),
+    },
+    OpcodeSpec {
+        name: "jnz",
+        operands: &[OperandType::Value, OperandType::Label],
+        cycles: 1,
+        effect: |registers, operands| {
+            if operands[0].value(registers) != 0 {
+                Some(operands[1].target())
+            } else {
+                None
+            }
+        },
+    },
+];
+
+struct Instruction {
+    opcode: &'static OpcodeSpec,
+    operands: Vec<Operand>,
+}
+
+fn parse_operand(
+    word: &str,
+    operand_type: OperandType,
+    labels: &HashMap<&str, usize>,
+) -> Result<Operand, String> {
+    let register = REGISTER_NAMES.iter().position(|&name| name == word);
+
+    match (operand_type, register) {
+        (OperandType::Register, Some(register)) | (OperandType::Value, Some(register)) => {
+            Ok(Operand::Register(register))
+        }
+        (OperandType::Register, None) => Err(format!("Invalid register '{}'", word)),
+        (OperandType::Value, None) => word
+            .parse::<i32>()
+            .map(Operand::Immediate)
+            .map_err(|_| format!("Invalid register or number '{}'", word)),
+        (OperandType::Label, _) => labels
+            .get(word)
+            .map(|&target| Operand::Target(target))
+            .ok_or_else(|| format!("Unknown label '{}'", word)),
+    }
+}
+
+// Parses one instruction per line. A line `name:` labels the instruction after it.
+fn parse_program(
+    input: &str,
+    instruction_set: &'static [OpcodeSpec],
+) -> Result<Vec<Instruction>, String> {
+    let lines: Vec<(usize, &str)> = input
+        .lines()
+        .enumerate()
+        .map(|(id, line)| (id + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+
+    let mut labels = HashMap::new();
+    let mut instruction_count = 0;
+    for &(line_number, line) in &lines {
+        match line.strip_suffix(':') {
+            Some(label) => {
+                if labels.insert(label, instruction_count).is_some() {
+                    return Err(format!("Line {}: Duplicate label '{}'", line_number, label));
+                }
+            }
+            None => instruction_count += 1,
+        }
+    }
+
+    let mut program = Vec::new();
+    for (line_number, line) in lines {
+        if line.ends_with(':') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap();
+        let opcode = instruction_set
+            .iter()
+            .find(|opcode| opcode.name == name)
+            .ok_or_else(|| format!("Line {}: invalid opcode '{}'", line_number, name))?;
+
+        let words: Vec<&str> = words.collect();
+        if words.len() != opcode.operands.len() {
+            return Err(format!(
+                "Line {}: '{}' takes {} operands, got {}",
+                line_number,
+                name,
+                opcode.operands.len(),
+                words.len()
+            ));
+        }
+
+        let operands = words
+            .iter()
+            .zip(opcode.operands)
+            .map(|(word, &operand_type)| parse_operand(word, operand_type, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Line {}: {}", line_number, err))?;
+
+        program.push(Instruction { opcode, operands });
+    }
+
+    Ok(program)
+}
+
+// Called during a cycle with the (1-based) cycle number and the register values
+type HookFn = Box<dyn FnMut(usize, &Registers)>;
 
-    return -1;
+struct Hook {
+    // `None` fires during every cycle
+    cycle: Option<usize>,
+    action: HookFn,
 }
 
-enum Opcode {
-    Noop,
-    Addx,
+#[derive(Debug, PartialEq, Eq)]
+enum Stop {
+    Halted,
+    // the cycle that is about to start
+    Breakpoint(usize),
 }
 
-type Instruction = (Opcode, i32);
+// Cycles during which the signal strength is sampled: `first`, `first + every`, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sampling {
+    first: usize,
+    every: usize,
+}
 
-fn line_to_instruction(line: &str) -> Instruction {
-    let mut words = line.trim().split_whitespace();
-    let opcode = match words.next() {
-        Some("noop") => Opcode::Noop,
-        Some("addx") => Opcode::Addx,
-        _ => panic!("Invalid opcode for line: {}", line.trim()),
-    };
+impl Sampling {
+    fn contains(&self, cycle: usize) -> bool {
+        cycle >= self.first && (cycle - self.first).is_multiple_of(self.every)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Framebuffer {
+    width: usize,
+    height: usize,
+    // row-major, `true` for lit pixels
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
 
-    let operand = match opcode {
-        Opcode::Noop => 0,
-        Opcode::Addx => words.next().unwrap().parse::<i32>().unwrap(),
-    };
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(self.width)
+    }
 
-    (opcode, operand)
+    // plain (P1) PBM image, lit pixels are black
+    fn to_pbm(&self) -> String {
+        let mut image = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.rows() {
+            let line: Vec<&str> = row.iter().map(|&lit| if lit { "1" } else { "0" }).collect();
+            image.push_str(&line.join(" "));
+            image.push('\n');
+        }
+        image
+    }
 }
 
-fn duration(opcode: &Opcode) -> i32 {
-    match opcode {
-        Opcode::Noop => 1,
-        Opcode::Addx => 2,
+// '#' for lit pixels and ' ' for dark ones, every row ends with a newline
+impl std::fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for row in self.rows() {
+            let line: String = row.iter().map(|&lit| if lit { '#' } else { ' ' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// The display of the handheld. The beam draws one pixel per cycle, row by row, and
+// starts over at the top left once the frame is full.
+struct Crt {
+    // pixel offsets relative to the x register that are covered by the sprite
+    sprite: Vec<i32>,
+    sampling: Sampling,
+    framebuffer: Framebuffer,
+    // (cycle, x) for every sampled cycle
+    samples: Vec<(usize, i32)>,
+}
+
+impl Crt {
+    fn new(width: usize, height: usize, sprite: Vec<i32>, sampling: Sampling) -> Crt {
+        assert!(width > 0 && height > 0);
+        assert!(sampling.every > 0);
+
+        Crt {
+            sprite,
+            sampling,
+            framebuffer: Framebuffer::new(width, height),
+            samples: Vec::new(),
+        }
+    }
+
+    // called during every (1-based) cycle with the current value of x
+    fn tick(&mut self, cycle: usize, x: i32) {
+        let framebuffer = &mut self.framebuffer;
+        let position = (cycle - 1) % framebuffer.pixels.len();
+        let column = (position % framebuffer.width) as i32;
+
+        framebuffer.pixels[position] = self.sprite.iter().any(|offset| x + offset == column);
+
+        if self.sampling.contains(cycle) {
+            self.samples.push((cycle, x));
+        }
+    }
+
+    fn signal_sum(&self) -> i32 {
+        self.samples
+            .iter()
+            .map(|&(cycle, x)| cycle as i32 * x)
+            .sum()
+    }
+}
+
+impl Default for Crt {
+    fn default() -> Crt {
+        Crt::new(
+            40,
+            6,
+            vec![-1, 0, 1],
+            Sampling {
+                first: 20,
+                every: 40,
+            },
+        )
     }
 }
 
 struct CPU {
-    x: i32,
+    registers: Registers,
     cycle: usize,
+    pc: usize,
+    // cycles already spent on the instruction at `pc`
+    elapsed: usize,
     history: Vec<i32>,
     program: Vec<Instruction>,
-    output: String,
+    crt: Crt,
+    breakpoints: HashSet<usize>,
+    hooks: Vec<Hook>,
+    // breakpoint that stopped the last run, so that resuming doesn't stop there again
+    stopped_at: Option<usize>,
 }
 
 impl CPU {
     fn new(program: Vec<Instruction>) -> CPU {
+        CPU::with_crt(program, Crt::default())
+    }
+
+    fn with_crt(program: Vec<Instruction>, crt: Crt) -> CPU {
+        let mut registers = [0; REGISTER_NAMES.len()];
+        registers[0] = 1;
+
         CPU {
-            x: 1,
+            registers,
             cycle: 0,
+            pc: 0,
+            elapsed: 0,
             history: vec![1],
             program,
-            output: String::new(),
+            crt,
+            breakpoints: HashSet::new(),
+            hooks: Vec::new(),
+            stopped_at: None,
         }
     }
 
-    fn screen_x(&self) -> usize {
-        self.cycle % 40
+    fn x(&self) -> i32 {
+        self.registers[0]
     }
 
-    fn is_lit(&self) -> bool {
-        (self.screen_x() as i32 - self.x).abs() <= 1
+    fn add_breakpoint(&mut self, cycle: usize) {
+        self.breakpoints.insert(cycle);
     }
 
-    fn run(&mut self) {
-        for (op, arg) in self.program.iter() {
-            let duration = duration(op);
-            for _ in 0..duration {
-                /* before cycle */
-
-                /* during cycle */
-                self.history.push(self.x);
+    fn on_cycle(&mut self, cycle: usize, action: impl FnMut(usize, &Registers) + 'static) {
+        self.hooks.push(Hook {
+            cycle: Some(cycle),
+            action: Box::new(action),
+        });
+    }
 
-                let c = if self.is_lit() { '#' } else { ' ' };
-                self.output.push(c);
+    fn on_every_cycle(&mut self, action: impl FnMut(usize, &Registers) + 'static) {
+        self.hooks.push(Hook {
+            cycle: None,
+            action: Box::new(action),
+        });
+    }
 
-                self.cycle += 1;
-                if self.screen_x() == 0 {
-                    self.output.push('\n');
-                }
+    fn step(&mut self) {
+        /* during cycle */
+        let cycle = self.cycle + 1;
+        let registers = self.registers;
+        for hook in self.hooks.iter_mut() {
+            if hook.cycle.is_none_or(|hook_cycle| hook_cycle == cycle) {
+                (hook.action)(cycle, &registers);
             }
+        }
+
+        self.history.push(self.x());
+        self.crt.tick(cycle, self.x());
+
+        self.cycle += 1;
 
-            /* after cycle */
-            match op {
-                Opcode::Noop => {}
-                Opcode::Addx => self.x += arg,
+        /* after cycle */
+        self.elapsed += 1;
+        let instruction = &self.program[self.pc];
+        if self.elapsed == instruction.opcode.cycles {
+            self.elapsed = 0;
+            self.pc = (instruction.opcode.effect)(&mut self.registers, &instruction.operands)
+                .unwrap_or(self.pc + 1);
+        }
+    }
+
+    // Runs until the program ends or a breakpoint is reached. Call again to resume.
+    fn run(&mut self) -> Stop {
+        while self.pc < self.program.len() {
+            let cycle = self.cycle + 1;
+            if self.breakpoints.contains(&cycle) && self.stopped_at != Some(cycle) {
+                self.stopped_at = Some(cycle);
+                return Stop::Breakpoint(cycle);
             }
+
+            self.step();
         }
+
+        Stop::Halted
     }
 
     fn from_str(input: &str) -> CPU {
-        let mut program = Vec::new();
-        for line in input.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
-            program.push(line_to_instruction(line));
-        }
+        let program = parse_program(input, INSTRUCTION_SET).unwrap_or_else(|err| panic!("{}", err));
         CPU::new(program)
     }
 
@@ -118,13 +627,7 @@ impl CPU {
     }
 
     fn signal_sum(&self) -> i32 {
-        self.history
-            .iter()
-            .enumerate()
-            .skip(20)
-            .step_by(40)
-            .map(|(i, x)| x * i as i32)
-            .sum()
+        self.crt.signal_sum()
     }
 }
 
@@ -284,10 +787,10 @@ noop
     fn test_part1() {
         let cpu = CPU::run_str("");
 
-        assert_eq!(cpu.x, 1);
+        assert_eq!(cpu.x(), 1);
         assert_eq!(cpu.cycle, 0);
 
-        assert_eq!(cpu.history[cpu.cycle], cpu.x);
+        assert_eq!(cpu.history[cpu.cycle], cpu.x());
 
         let cpu = CPU::run_str(TEST_INPUT);
 
@@ -312,6 +815,150 @@ noop
     fn test_part2() {
         let cpu = CPU::run_str(TEST_INPUT);
 
-        assert_eq!(cpu.output, REFERENCE_OUTPUT);
+        assert_eq!(cpu.crt.framebuffer.to_string(), REFERENCE_OUTPUT);
+    }
+
+    const LOOP_PROGRAM: &str = "set y 3
+loop:
+addx 2
+add y -1
+jnz y loop
+mul x y";
+
+    #[test]
+    fn test_vm() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut cpu = CPU::from_str(LOOP_PROGRAM);
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_by_hook = Rc::clone(&seen);
+        cpu.on_cycle(4, move |cycle, registers| {
+            seen_by_hook
+                .borrow_mut()
+                .push((cycle, registers[0], registers[1]))
+        });
+
+        let cycles = Rc::new(RefCell::new(0));
+        let cycles_by_hook = Rc::clone(&cycles);
+        cpu.on_every_cycle(move |_, _| *cycles_by_hook.borrow_mut() += 1);
+
+        cpu.add_breakpoint(4);
+        cpu.add_breakpoint(100);
+
+        assert_eq!(cpu.run(), Stop::Breakpoint(4));
+        assert_eq!(cpu.cycle, 3);
+        assert_eq!(cpu.x(), 3);
+        assert!(seen.borrow().is_empty());
+
+        assert_eq!(cpu.run(), Stop::Halted);
+        assert_eq!(*seen.borrow(), vec![(4, 3, 3)]);
+        assert_eq!(cpu.cycle, 1 + 3 * 5 + 3);
+        assert_eq!(*cycles.borrow(), cpu.cycle);
+        assert_eq!(cpu.registers, [0, 0, 0, 0]);
+        assert_eq!(cpu.history[cpu.cycle], 7);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |input| parse_program(input, INSTRUCTION_SET).err().unwrap();
+
+        assert_eq!(err("noop\nfoo 1"), "Line 2: invalid opcode 'foo'");
+        assert_eq!(err("addx"), "Line 1: 'addx' takes 1 operands, got 0");
+        assert_eq!(err("\nset q 1"), "Line 2: Invalid register 'q'");
+        assert_eq!(err("add x a"), "Line 1: Invalid register or number 'a'");
+        assert_eq!(err("jmp nowhere"), "Line 1: Unknown label 'nowhere'");
+        assert_eq!(
+            err("loop:\nnoop\nloop:\njmp loop"),
+            "Line 3: Duplicate label 'loop'"
+        );
+    }
+
+    #[test]
+    fn test_ocr() {
+        let glyph = |letter| FONT.iter().find(|(l, _)| *l == letter).unwrap().1;
+        let screen = (0..GLYPH_HEIGHT)
+            .map(|row| {
+                "HELLO"
+                    .chars()
+                    .map(|letter| glyph(letter).lines().nth(row).unwrap().replace('.', " "))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    + " "
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert_eq!(ocr(&screen), Ok("HELLO".to_string()));
+
+        assert_eq!(
+            ocr(REFERENCE_OUTPUT),
+            Err("Unknown glyph 0 at columns 0..4:\n##..\n###.\n####\n####\n####\n####".to_string())
+        );
+        assert!(ocr("#").is_err());
+
+        // the trailing blank column is optional, a partial glyph is not
+        let trimmed: Vec<&str> = screen.lines().map(|row| &row[..row.len() - 1]).collect();
+        assert_eq!(ocr(&trimmed.join("\n")), Ok("HELLO".to_string()));
+        let partial: Vec<&str> = screen.lines().map(|row| &row[..row.len() - 3]).collect();
+        assert_eq!(
+            ocr(&partial.join("\n")),
+            Err("Incomplete glyph 4 at columns 20..22".to_string())
+        );
+        let extra: Vec<String> = screen.lines().map(|row| format!("{}#", row)).collect();
+        assert_eq!(
+            ocr(&extra.join("\n")),
+            Err("Incomplete glyph 5 at columns 25..26".to_string())
+        );
+    }
+
+    #[test]
+    fn test_crt() {
+        let program = parse_program(TEST_INPUT, INSTRUCTION_SET).unwrap();
+        let crt = Crt::new(
+            20,
+            3,
+            vec![0, 1],
+            Sampling {
+                first: 1,
+                every: 100,
+            },
+        );
+        let mut cpu = CPU::with_crt(program, crt);
+        cpu.run();
+
+        // 240 cycles on a 60 pixel screen: four full frames, the last one stays
+        assert_eq!(
+            cpu.crt.samples,
+            vec![(1, 1), (101, cpu.history[101]), (201, cpu.history[201])]
+        );
+        assert_eq!(
+            cpu.signal_sum(),
+            1 + 101 * cpu.history[101] + 201 * cpu.history[201]
+        );
+
+        let last_frame: String = (181..=240)
+            .map(|cycle| {
+                let column = ((cycle - 1) % 20) as i32;
+                let x = cpu.history[cycle];
+                if column == x || column == x + 1 {
+                    '#'
+                } else {
+                    ' '
+                }
+            })
+            .collect::<Vec<char>>()
+            .chunks(20)
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect();
+        assert_eq!(cpu.crt.framebuffer.to_string(), last_frame);
+
+        let framebuffer = Framebuffer {
+            width: 3,
+            height: 2,
+            pixels: vec![true, false, true, false, true, false],
+        };
+        assert_eq!(framebuffer.to_string(), "# #\n # \n");
+        assert_eq!(framebuffer.to_pbm(), "P1\n3 2\n1 0 1\n0 1 0\n");
     }
 }
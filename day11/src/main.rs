@@ -1,182 +1,823 @@
+use num_bigint::BigInt;
 use sscanf::sscanf;
-use std::fs;
+use std::{collections::HashMap, env, fs, hash::Hash, rc::Rc, str::FromStr};
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
 
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
+
+    // `cargo run -- history <rounds>` prints what happens in every round of part 1
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 && args[1] == "history" {
+        let rounds = args[2].parse::<usize>().unwrap();
+        let relief = "old / 3".parse::<Operation>().unwrap();
+        let history =
+            inspection_history(&parse_monkeys(&input), rounds, &relief, |item| item).unwrap();
+
+        for snapshot in history.iter().skip(1) {
+            println!("== Round {} ==", snapshot.round);
+            for throw in &snapshot.throws {
+                println!(
+                    "Monkey {} throws {} to monkey {}",
+                    throw.from, throw.item, throw.to
+                );
+            }
+            for (id, items) in snapshot.items.iter().enumerate() {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                println!(
+                    "Monkey {} ({} inspected): {}",
+                    id,
+                    snapshot.items_inspected[id],
+                    items.join(", ")
+                );
+            }
+        }
+        return;
+    }
+
+    // `cargo run -- <checked|big|residues|lcm> <rounds> [relief]` runs the simulation
+    // with the given worry arithmetic, e.g. `cargo run -- big 20 "old / 3"`. Residues are
+    // fast-forwarded, so `cargo run -- residues 1000000000` works as well.
+    if args.len() > 2 {
+        let arithmetic = args[1].parse::<Arithmetic>().unwrap();
+        let rounds = args[2].parse::<usize>().unwrap();
+        let relief = args.get(3).map_or(Operation::Old, |relief| {
+            relief.parse::<Operation>().unwrap()
+        });
+
+        match monkey_business(&parse_monkeys(&input), rounds, &relief, arithmetic) {
+            Ok(level) => println!("Monkey business ({}): {}", args[1], level),
+            Err(err) => println!("Monkey business ({}) failed in {}", args[1], err),
+        }
+    }
 }
 
 fn part1(input: &str) -> usize {
-    let mut monkeys = parse_monkeys(input);
+    let monkeys = inspection_rounds_part1(&parse_monkeys(input), 20);
 
-    inspection_rounds_part1(&mut monkeys, 20);
-
-    return monkey_business_level(&monkeys) as usize;
+    monkey_business_level(&monkeys)
 }
 
 fn part2(input: &str) -> usize {
-    let mut monkeys = parse_monkeys(input);
-
-    inspection_rounds_part2(&mut monkeys, 10_000);
+    let monkeys = inspection_rounds_part2(&parse_monkeys(input), 10_000);
 
-    return monkey_business_level(&monkeys) as usize;
+    monkey_business_level(&monkeys)
 }
 
-#[derive(Debug)]
+// Worry level update of a monkey, parsed from the right hand side of
+// `Operation: new = ...`. Any arithmetic expression over `old` and constants.
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Operation {
-    Add(i64),
-    Multiply(i64),
-    Divide(i64),
-    Subtract(i64),
-    Square(),
-    Identity(),
+    Old,
+    Constant(i64),
+    Negate(Box<Operation>),
+    Add(Box<Operation>, Box<Operation>),
+    Subtract(Box<Operation>, Box<Operation>),
+    Multiply(Box<Operation>, Box<Operation>),
+    Divide(Box<Operation>, Box<Operation>),
+    Remainder(Box<Operation>, Box<Operation>),
 }
 
 impl Operation {
-    fn apply(&self, input: i64) -> i64 {
+    fn evaluate<W: Worry>(&self, old: &W) -> Result<W, WorryError> {
         match self {
-            Operation::Add(arg) => input + arg,
-            Operation::Multiply(arg) => input * arg,
-            Operation::Divide(arg) => input / arg,
-            Operation::Subtract(arg) => input - arg,
-            Operation::Square() => input * input,
-            Operation::Identity() => input,
+            Operation::Old => Ok(old.clone()),
+            Operation::Constant(value) => Ok(old.constant(*value)),
+            Operation::Negate(arg) => arg.evaluate(old)?.neg(),
+            Operation::Add(lhs, rhs) => lhs.evaluate(old)?.add(&rhs.evaluate(old)?),
+            Operation::Subtract(lhs, rhs) => lhs.evaluate(old)?.sub(&rhs.evaluate(old)?),
+            Operation::Multiply(lhs, rhs) => lhs.evaluate(old)?.mul(&rhs.evaluate(old)?),
+            Operation::Divide(lhs, rhs) => lhs.evaluate(old)?.div(&rhs.evaluate(old)?),
+            Operation::Remainder(lhs, rhs) => lhs.evaluate(old)?.rem(&rhs.evaluate(old)?),
         }
     }
+
+    // plain i64 arithmetic, panics on overflow
+    #[cfg(test)]
+    fn apply(&self, input: i64) -> i64 {
+        self.evaluate(&input).unwrap()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Old,
+    Number(i64),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                number.push(digit);
+                chars.next();
+            }
+            let number = number
+                .parse::<i64>()
+                .map_err(|_| format!("Number too large: {}", number))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() {
+            let mut word = String::new();
+            while let Some(&letter) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                word.push(letter);
+                chars.next();
+            }
+            if word != "old" {
+                return Err(format!("Unknown variable '{}'", word));
+            }
+            tokens.push(Token::Old);
+        } else if "+-*/%()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent over the usual precedence levels:
+// sum := product (('+' | '-') product)*
+// product := unary (('*' | '/' | '%') unary)*
+// unary := '-' unary | 'old' | number | '(' sum ')'
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn sum(&mut self) -> Result<Operation, String> {
+        let mut lhs = self.product()?;
+        while let Some(Token::Symbol(op @ ('+' | '-'))) = self.peek() {
+            self.next();
+            let rhs = Box::new(self.product()?);
+            lhs = match op {
+                '+' => Operation::Add(Box::new(lhs), rhs),
+                _ => Operation::Subtract(Box::new(lhs), rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Operation, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Symbol(op @ ('*' | '/' | '%'))) = self.peek() {
+            self.next();
+            let rhs = Box::new(self.unary()?);
+            lhs = match op {
+                '*' => Operation::Multiply(Box::new(lhs), rhs),
+                '/' => Operation::Divide(Box::new(lhs), rhs),
+                _ => Operation::Remainder(Box::new(lhs), rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Operation, String> {
+        match self.next() {
+            Some(Token::Symbol('-')) => Ok(Operation::Negate(Box::new(self.unary()?))),
+            Some(Token::Old) => Ok(Operation::Old),
+            Some(Token::Number(value)) => Ok(Operation::Constant(value)),
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                match self.next() {
+                    Some(Token::Symbol(')')) => Ok(inner),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            }
+            Some(Token::Symbol(c)) => Err(format!("Unexpected '{}'", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = ExpressionParser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+
+        let operation = parser.sum()?;
+        match parser.peek() {
+            None => Ok(operation),
+            Some(Token::Symbol(c)) => Err(format!("Unexpected '{}'", c)),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Test {
     DivisibleBy(i64),
+    GreaterThan(i64),
+    LessThan(i64),
+    EqualTo(i64),
 }
 
 impl Test {
     fn perform(&self, input: i64) -> bool {
         match self {
-            Test::DivisibleBy(divisor) => input % divisor == 0,
+            // wrapping, as i64::MIN % -1 overflows although the remainder is 0
+            Test::DivisibleBy(divisor) => input.wrapping_rem(*divisor) == 0,
+            Test::GreaterThan(value) => input > *value,
+            Test::LessThan(value) => input < *value,
+            Test::EqualTo(value) => input == *value,
+        }
+    }
+}
+
+impl FromStr for Test {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Ok(divisor) = sscanf!(input, "divisible by {i64}") {
+            if divisor == 0 {
+                return Err("Cannot test for divisibility by 0".to_string());
+            }
+            Ok(Test::DivisibleBy(divisor))
+        } else if let Ok(value) = sscanf!(input, "greater than {i64}") {
+            Ok(Test::GreaterThan(value))
+        } else if let Ok(value) = sscanf!(input, "less than {i64}") {
+            Ok(Test::LessThan(value))
+        } else if let Ok(value) = sscanf!(input, "equal to {i64}") {
+            Ok(Test::EqualTo(value))
+        } else {
+            Err(format!("Unknown test '{}'", input))
         }
     }
 }
 
+// Where a monkey throws an item. The tests are checked in order and the first one
+// that holds picks the target. If none does, the item goes to `otherwise`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ThrowTable {
+    rules: Vec<(Test, usize)>,
+    otherwise: usize,
+}
+
+impl ThrowTable {
+    fn target<W: Worry>(&self, input: &W) -> Result<usize, WorryError> {
+        for &(test, target) in &self.rules {
+            if input.test(&test)? {
+                return Ok(target);
+            }
+        }
+        Ok(self.otherwise)
+    }
+
+    fn targets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.rules
+            .iter()
+            .map(|&(_, target)| target)
+            .chain(std::iter::once(self.otherwise))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
 #[derive(Debug)]
-struct Monkey {
+struct Monkey<W = i64> {
     id: usize,
-    items: Vec<i64>,
+    items: Vec<W>,
     operation: Operation,
-    test: Test,
-    if_true: usize,
-    if_false: usize,
+    throws: ThrowTable,
     items_inspected: usize,
 }
 
 impl Monkey {
-    fn new() -> Monkey {
+    // a copy with the starting items converted to another worry arithmetic
+    fn with_worry<W>(&self, to_worry: impl Fn(i64) -> W) -> Monkey<W> {
         Monkey {
-            id: 0,
-            items: Vec::new(),
-            operation: Operation::Add(0),
-            test: Test::DivisibleBy(1),
-            if_true: 0,
-            if_false: 0,
-            items_inspected: 0,
+            id: self.id,
+            items: self.items.iter().map(|&item| to_worry(item)).collect(),
+            operation: self.operation.clone(),
+            throws: self.throws.clone(),
+            items_inspected: self.items_inspected,
         }
     }
+}
 
-    fn from_str(input: &str) -> Monkey {
-        let mut monkey = Monkey::new();
-
-        for line in input.lines() {
-            if let Ok(id) = sscanf!(line, "Monkey {usize}:") {
-                monkey.id = id;
-            } else if let Ok(parsed) = sscanf!(line, "  Starting items: {str}") {
-                monkey.items = parsed
-                    .split(", ")
-                    .map(|s| s.parse::<i64>().unwrap())
-                    .collect();
-            } else if let Ok((op, arg)) = sscanf!(line, "  Operation: new = old {char} {i64}") {
-                monkey.operation = match op {
-                    '+' => Operation::Add(arg),
-                    '*' => Operation::Multiply(arg),
-                    '/' => Operation::Divide(arg),
-                    '-' => Operation::Subtract(arg),
-                    _ => panic!("Unknown operation: {}", op),
-                };
-            } else if line == "  Operation: new = old * old" {
-                monkey.operation = Operation::Square();
-            } else if let Ok(divisor) = sscanf!(line, "  Test: divisible by {i64}") {
-                monkey.test = Test::DivisibleBy(divisor);
-            } else if let Ok(target) = sscanf!(line, "    If true: throw to monkey {usize}") {
-                monkey.if_true = target;
-            } else if let Ok(target) = sscanf!(line, "    If false: throw to monkey {usize}") {
-                monkey.if_false = target;
+impl Monkey {
+    // Parses a single monkey from (line number, line) pairs. A monkey may chain several
+    // tests, each followed by its `If true:` line. Only the last test may have an
+    // `If false:` line, which is where items go that pass none of the tests.
+    fn parse(lines: &[(usize, &str)]) -> Result<Monkey, ParseError> {
+        let (header_line, header) = lines[0];
+        let error = |line: usize, message: String| ParseError { line, message };
+
+        let id = sscanf!(header.trim(), "Monkey {usize}:").map_err(|_| {
+            error(
+                header_line,
+                format!("Expected 'Monkey <id>:', got '{}'", header),
+            )
+        })?;
+
+        let mut items = None;
+        let mut operation = None;
+        let mut rules: Vec<(Test, usize)> = Vec::new();
+        let mut pending_test: Option<Test> = None;
+        let mut otherwise = None;
+
+        for &(line_number, line) in &lines[1..] {
+            let line = line.trim();
+
+            if otherwise.is_some() {
+                return Err(error(
+                    line_number,
+                    "Nothing may follow the 'If false:' line".to_string(),
+                ));
+            }
+
+            if let Ok(parsed) = sscanf!(line, "Starting items: {str}") {
+                items = Some(
+                    parsed
+                        .split(',')
+                        .map(|s| s.trim().parse::<i64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| error(line_number, format!("Invalid item: {}", err)))?,
+                );
+            } else if line == "Starting items:" {
+                items = Some(Vec::new());
+            } else if let Ok(expression) = sscanf!(line, "Operation: new = {str}") {
+                operation = Some(
+                    expression
+                        .parse::<Operation>()
+                        .map_err(|err| error(line_number, err))?,
+                );
+            } else if let Ok(test) = sscanf!(line, "Test: {str}") {
+                if pending_test.is_some() {
+                    return Err(error(
+                        line_number,
+                        "Test without 'If true:' line".to_string(),
+                    ));
+                }
+                pending_test = Some(
+                    test.parse::<Test>()
+                        .map_err(|err| error(line_number, err))?,
+                );
+            } else if let Ok(target) = sscanf!(line, "If true: throw to monkey {usize}") {
+                let test = pending_test
+                    .take()
+                    .ok_or_else(|| error(line_number, "'If true:' without a test".to_string()))?;
+                rules.push((test, target));
+            } else if let Ok(target) = sscanf!(line, "If false: throw to monkey {usize}") {
+                if rules.is_empty() || pending_test.is_some() {
+                    return Err(error(line_number, "'If false:' without a test".to_string()));
+                }
+                otherwise = Some(target);
             } else {
-                panic!("Unmatched monkey definition line:\n'{}'", line);
+                return Err(error(
+                    line_number,
+                    format!("Unmatched monkey definition line '{}'", line),
+                ));
             }
         }
 
-        assert_ne!(monkey.if_true, monkey.id);
-        assert_ne!(monkey.if_false, monkey.id);
+        let missing = |what: &str| error(header_line, format!("Monkey {} has no {}", id, what));
+
+        let monkey = Monkey {
+            id,
+            items: items.ok_or_else(|| missing("starting items"))?,
+            operation: operation.ok_or_else(|| missing("operation"))?,
+            throws: ThrowTable {
+                rules,
+                otherwise: otherwise.ok_or_else(|| missing("'If false:' line"))?,
+            },
+            items_inspected: 0,
+        };
+
+        if monkey.throws.targets().any(|target| target == id) {
+            return Err(error(
+                header_line,
+                format!("Monkey {} throws to itself", id),
+            ));
+        }
+
+        Ok(monkey)
+    }
+}
+
+fn try_parse_monkeys(input: &str) -> Result<Vec<Monkey>, ParseError> {
+    let mut monkeys: Vec<Monkey> = Vec::new();
+    let mut header_lines = Vec::new();
+    let mut block: Vec<(usize, &str)> = Vec::new();
+
+    let lines = input.lines().map(|line| line.trim_end()).enumerate();
+    for (id, line) in lines.chain(std::iter::once((usize::MAX, ""))) {
+        if !line.is_empty() {
+            block.push((id + 1, line));
+            continue;
+        }
+        if block.is_empty() {
+            continue;
+        }
+
+        let monkey = Monkey::parse(&block)?;
+        if monkey.id != monkeys.len() {
+            return Err(ParseError {
+                line: block[0].0,
+                message: format!(
+                    "Expected monkey {}, got monkey {}",
+                    monkeys.len(),
+                    monkey.id
+                ),
+            });
+        }
+
+        header_lines.push(block[0].0);
+        monkeys.push(monkey);
+        block.clear();
+    }
 
-        return monkey;
+    for (monkey, line) in monkeys.iter().zip(header_lines) {
+        if let Some(target) = monkey
+            .throws
+            .targets()
+            .find(|&target| target >= monkeys.len())
+        {
+            return Err(ParseError {
+                line,
+                message: format!("Monkey {} throws to unknown monkey {}", monkey.id, target),
+            });
+        }
     }
+
+    Ok(monkeys)
 }
 
 fn parse_monkeys(input: &str) -> Vec<Monkey> {
-    input.split("\n\n").map(|s| Monkey::from_str(s)).collect()
+    try_parse_monkeys(input).unwrap_or_else(|err| panic!("Invalid monkeys, {}", err))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorryError {
+    Overflow,
+    DivisionByZero,
+    // the arithmetic cannot perform this operation or test
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for WorryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WorryError::Overflow => write!(f, "worry level overflow"),
+            WorryError::DivisionByZero => write!(f, "division by zero"),
+            WorryError::Unsupported(what) => write!(f, "{} not supported", what),
+        }
+    }
+}
+
+// Arithmetic used for worry levels. Operations that the representation cannot do
+// (or that would overflow) return an error instead of a wrong result.
+trait Worry: Clone + std::fmt::Debug {
+    // a constant in the same representation as `self`
+    fn constant(&self, value: i64) -> Self;
+    fn neg(&self) -> Result<Self, WorryError>;
+    fn add(&self, rhs: &Self) -> Result<Self, WorryError>;
+    fn sub(&self, rhs: &Self) -> Result<Self, WorryError>;
+    fn mul(&self, rhs: &Self) -> Result<Self, WorryError>;
+    fn div(&self, rhs: &Self) -> Result<Self, WorryError>;
+    fn rem(&self, rhs: &Self) -> Result<Self, WorryError>;
+    fn test(&self, test: &Test) -> Result<bool, WorryError>;
+}
+
+// checked i64 arithmetic
+impl Worry for i64 {
+    fn constant(&self, value: i64) -> Self {
+        value
+    }
+
+    fn neg(&self) -> Result<Self, WorryError> {
+        self.checked_neg().ok_or(WorryError::Overflow)
+    }
+
+    fn add(&self, rhs: &Self) -> Result<Self, WorryError> {
+        self.checked_add(*rhs).ok_or(WorryError::Overflow)
+    }
+
+    fn sub(&self, rhs: &Self) -> Result<Self, WorryError> {
+        self.checked_sub(*rhs).ok_or(WorryError::Overflow)
+    }
+
+    fn mul(&self, rhs: &Self) -> Result<Self, WorryError> {
+        self.checked_mul(*rhs).ok_or(WorryError::Overflow)
+    }
+
+    fn div(&self, rhs: &Self) -> Result<Self, WorryError> {
+        match rhs {
+            0 => Err(WorryError::DivisionByZero),
+            _ => self.checked_div(*rhs).ok_or(WorryError::Overflow),
+        }
+    }
+
+    fn rem(&self, rhs: &Self) -> Result<Self, WorryError> {
+        match rhs {
+            0 => Err(WorryError::DivisionByZero),
+            _ => self.checked_rem(*rhs).ok_or(WorryError::Overflow),
+        }
+    }
+
+    fn test(&self, test: &Test) -> Result<bool, WorryError> {
+        match *test {
+            // i64::MIN % -1 overflows
+            Test::DivisibleBy(divisor) => self
+                .checked_rem(divisor)
+                .map(|remainder| remainder == 0)
+                .ok_or(WorryError::Overflow),
+            _ => Ok(test.perform(*self)),
+        }
+    }
+}
+
+impl Worry for BigInt {
+    fn constant(&self, value: i64) -> Self {
+        BigInt::from(value)
+    }
+
+    fn neg(&self) -> Result<Self, WorryError> {
+        Ok(-self)
+    }
+
+    fn add(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self + rhs)
+    }
+
+    fn sub(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self - rhs)
+    }
+
+    fn mul(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self * rhs)
+    }
+
+    fn div(&self, rhs: &Self) -> Result<Self, WorryError> {
+        match *rhs == BigInt::from(0) {
+            true => Err(WorryError::DivisionByZero),
+            false => Ok(self / rhs),
+        }
+    }
+
+    fn rem(&self, rhs: &Self) -> Result<Self, WorryError> {
+        match *rhs == BigInt::from(0) {
+            true => Err(WorryError::DivisionByZero),
+            false => Ok(self % rhs),
+        }
+    }
+
+    fn test(&self, test: &Test) -> Result<bool, WorryError> {
+        Ok(match *test {
+            Test::DivisibleBy(divisor) => self % divisor == BigInt::from(0),
+            Test::GreaterThan(value) => *self > BigInt::from(value),
+            Test::LessThan(value) => *self < BigInt::from(value),
+            Test::EqualTo(value) => *self == BigInt::from(value),
+        })
+    }
+}
+
+// Residue number representation: the worry level modulo each of `moduli`. Supports
+// +, - and * and divisibility by any divisor of one of the moduli, nothing else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Residues {
+    moduli: Rc<Vec<i64>>,
+    values: Vec<i64>,
+}
+
+impl Residues {
+    fn new(value: i64, moduli: &Rc<Vec<i64>>) -> Residues {
+        Residues {
+            moduli: Rc::clone(moduli),
+            values: moduli.iter().map(|&m| value.rem_euclid(m)).collect(),
+        }
+    }
+
+    fn combine(&self, rhs: &Self, op: impl Fn(i128, i128) -> i128) -> Residues {
+        let values = self
+            .values
+            .iter()
+            .zip(&rhs.values)
+            .zip(self.moduli.iter())
+            .map(|((&a, &b), &m)| op(a as i128, b as i128).rem_euclid(m as i128) as i64)
+            .collect();
+
+        Residues {
+            moduli: Rc::clone(&self.moduli),
+            values,
+        }
+    }
 }
 
-fn inspection_round(monkeys: &mut Vec<Monkey>, intermediate_operation: &Operation) {
-    let mut passed_items: Vec<Vec<i64>> = monkeys.iter().map(|_| Vec::new()).collect();
+impl Worry for Residues {
+    fn constant(&self, value: i64) -> Self {
+        Residues::new(value, &self.moduli)
+    }
+
+    fn neg(&self) -> Result<Self, WorryError> {
+        Ok(self.constant(0).combine(self, |a, b| a - b))
+    }
+
+    fn add(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self.combine(rhs, |a, b| a + b))
+    }
+
+    fn sub(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self.combine(rhs, |a, b| a - b))
+    }
+
+    fn mul(&self, rhs: &Self) -> Result<Self, WorryError> {
+        Ok(self.combine(rhs, |a, b| a * b))
+    }
 
-    let divisors = monkeys
+    fn div(&self, _: &Self) -> Result<Self, WorryError> {
+        Err(WorryError::Unsupported("division of residues"))
+    }
+
+    fn rem(&self, _: &Self) -> Result<Self, WorryError> {
+        Err(WorryError::Unsupported("remainder of residues"))
+    }
+
+    fn test(&self, test: &Test) -> Result<bool, WorryError> {
+        match *test {
+            Test::DivisibleBy(divisor) => self
+                .moduli
+                .iter()
+                .zip(&self.values)
+                .find(|(&m, _)| m % divisor == 0)
+                .map(|(_, &value)| value % divisor == 0)
+                .ok_or(WorryError::Unsupported("divisibility by a non-modulus")),
+            _ => Err(WorryError::Unsupported("comparison of residues")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arithmetic {
+    // checked i64, reports overflow
+    Checked,
+    // arbitrary precision integers, exact but slow for big numbers
+    Big,
+    // one residue per divisor the monkeys test for
+    Residues,
+    // a single residue modulo the LCM of all divisors
+    Lcm,
+}
+
+impl FromStr for Arithmetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checked" => Ok(Arithmetic::Checked),
+            "big" => Ok(Arithmetic::Big),
+            "residues" => Ok(Arithmetic::Residues),
+            "lcm" => Ok(Arithmetic::Lcm),
+            _ => Err(format!("Unknown arithmetic: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SimulationError {
+    round: usize,
+    monkey: usize,
+    error: WorryError,
+}
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "round {}, monkey {}: {}",
+            self.round, self.monkey, self.error
+        )
+    }
+}
+
+// all divisors the monkeys test for, without duplicates
+fn divisors(monkeys: &[Monkey]) -> Vec<i64> {
+    let mut divisors: Vec<i64> = monkeys
         .iter()
-        .map(|m| match m.test {
-            Test::DivisibleBy(divisor) => divisor,
+        .flat_map(|m| m.throws.rules.iter())
+        .filter_map(|(test, _)| match test {
+            Test::DivisibleBy(divisor) => Some(divisor.abs()),
+            _ => None,
         })
-        .collect::<Vec<i64>>();
+        .collect();
+    divisors.sort();
+    divisors.dedup();
+    divisors
+}
 
-    let mut denominator: i64 = 1;
-    for divisor in divisors.iter() {
-        denominator = lcm(denominator, *divisor);
+// The least common multiple of all divisors the monkeys test for. An overflow
+// is reported for round 0 and the monkey whose divisor caused it.
+fn divisor_lcm(monkeys: &[Monkey]) -> Result<i64, SimulationError> {
+    let mut multiple = 1;
+    for (id, monkey) in monkeys.iter().enumerate() {
+        for (test, _) in &monkey.throws.rules {
+            if let Test::DivisibleBy(divisor) = test {
+                multiple = divisor
+                    .checked_abs()
+                    .ok_or(WorryError::Overflow)
+                    .and_then(|divisor| lcm(multiple, divisor))
+                    .map_err(|error| SimulationError {
+                        round: 0,
+                        monkey: id,
+                        error,
+                    })?;
+            }
+        }
     }
+    Ok(multiple)
+}
 
-    // println!("Denominator: {}", denominator);
+// A single inspection: the monkey the item is thrown to and its new worry level
+fn inspect<W: Worry>(
+    monkey: &Monkey<W>,
+    item: &W,
+    intermediate_operation: &Operation,
+) -> Result<(usize, W), WorryError> {
+    // 1. inspect = apply operation
+    let new_value = monkey.operation.evaluate(item)?;
+
+    // 2. perform intermediate operation, e.g. divide by 3
+    let divided_new_value = intermediate_operation.evaluate(&new_value)?;
+
+    // 3. test, throw to target
+    let target = monkey.throws.target(&divided_new_value)?;
+
+    Ok((target, divided_new_value))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Throw<W> {
+    from: usize,
+    to: usize,
+    // worry level of the item when it was thrown
+    item: W,
+}
+
+// One round of inspections. Every throw is appended to `log`, if given.
+fn inspection_round<W: Worry>(
+    monkeys: &mut [Monkey<W>],
+    intermediate_operation: &Operation,
+    mut log: Option<&mut Vec<Throw<W>>>,
+) -> Result<(), (usize, WorryError)> {
+    let mut passed_items: Vec<Vec<W>> = monkeys.iter().map(|_| Vec::new()).collect();
 
     for (monkey_id, monkey) in monkeys.iter_mut().enumerate() {
         // move passed items to the current monkey
         if monkey_id != monkey.id {
             panic!("Monkey ID mismatch: {} != {}", monkey_id, monkey.id);
         }
-        for item in passed_items[monkey_id].iter() {
-            monkey.items.push(*item);
-        }
-        passed_items[monkey_id].clear();
-
-        // three steps:
-        // 1. inspect = apply operation
-        // 2. perform intermediate operation, e.g. divide by 3
-        // 3. test, throw to target
+        monkey.items.append(&mut passed_items[monkey_id]);
 
         // inspect items
         for item in monkey.items.iter() {
             monkey.items_inspected += 1;
 
-            // 1. inspect
-            let new_value = monkey.operation.apply(*item) % denominator;
-
-            // 2. perform operation
-            let divided_new_value = intermediate_operation.apply(new_value);
+            let (target, value) =
+                inspect(monkey, item, intermediate_operation).map_err(|err| (monkey_id, err))?;
 
-            // 3. test, throw to target
-            let test_result = monkey.test.perform(divided_new_value);
-            let target = match test_result {
-                true => monkey.if_true,
-                false => monkey.if_false,
-            };
-
-            passed_items[target].push(divided_new_value);
+            if let Some(log) = log.as_mut() {
+                log.push(Throw {
+                    from: monkey_id,
+                    to: target,
+                    item: value.clone(),
+                });
+            }
+            passed_items[target].push(value);
         }
 
         // clear items
@@ -184,44 +825,228 @@ fn inspection_round(monkeys: &mut Vec<Monkey>, intermediate_operation: &Operatio
     }
 
     // move remaining passed items to the according monkeys
-    for (monkey_id, items) in passed_items.iter().enumerate() {
-        for item in items.iter() {
-            monkeys[monkey_id].items.push(*item);
-        }
+    for (monkey, items) in monkeys.iter_mut().zip(passed_items.iter_mut()) {
+        monkey.items.append(items);
+    }
+
+    Ok(())
+}
+
+fn inspection_rounds_part1(monkeys: &[Monkey], rounds: usize) -> Vec<Monkey> {
+    let op = "old / 3".parse::<Operation>().unwrap();
+    inspection_rounds_impl(monkeys, rounds, &op, |item| item)
+        .unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn inspection_rounds_part2(monkeys: &[Monkey], rounds: usize) -> Vec<Monkey<Residues>> {
+    let op = Operation::Old;
+    let moduli = Rc::new(divisors(monkeys));
+    inspection_rounds_impl(monkeys, rounds, &op, |item| Residues::new(item, &moduli))
+        .unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn inspection_rounds_impl<W: Worry>(
+    monkeys: &[Monkey],
+    rounds: usize,
+    intermediate_operation: &Operation,
+    to_worry: impl Fn(i64) -> W,
+) -> Result<Vec<Monkey<W>>, SimulationError> {
+    let mut monkeys: Vec<Monkey<W>> = monkeys.iter().map(|m| m.with_worry(&to_worry)).collect();
+
+    for round in 1..=rounds {
+        inspection_round(&mut monkeys, intermediate_operation, None).map_err(
+            |(monkey, error)| SimulationError {
+                round,
+                monkey,
+                error,
+            },
+        )?;
     }
+
+    Ok(monkeys)
 }
 
-fn inspection_rounds_part1(monkeys: &mut Vec<Monkey>, rounds: usize) {
-    let op = Operation::Divide(3);
-    inspection_rounds_impl(monkeys, rounds, &op)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RoundSnapshot<W> {
+    round: usize,
+    // items held by each monkey after the round
+    items: Vec<Vec<W>>,
+    // inspections per monkey so far
+    items_inspected: Vec<usize>,
+    // every throw of the round, in order
+    throws: Vec<Throw<W>>,
 }
 
-fn inspection_rounds_part2(monkeys: &mut Vec<Monkey>, rounds: usize) {
-    let op = Operation::Identity();
-    inspection_rounds_impl(monkeys, rounds, &op)
+fn snapshot<W: Clone>(
+    round: usize,
+    monkeys: &[Monkey<W>],
+    throws: Vec<Throw<W>>,
+) -> RoundSnapshot<W> {
+    RoundSnapshot {
+        round,
+        items: monkeys.iter().map(|m| m.items.clone()).collect(),
+        items_inspected: monkeys.iter().map(|m| m.items_inspected).collect(),
+        throws,
+    }
 }
 
-fn inspection_rounds_impl(
-    monkeys: &mut Vec<Monkey>,
+// The state before the first round, followed by a snapshot after every round
+fn inspection_history<W: Worry>(
+    monkeys: &[Monkey],
     rounds: usize,
     intermediate_operation: &Operation,
-) {
-    // print_monkeys(monkeys);
-    for _ in 0..rounds {
-        inspection_round(monkeys, intermediate_operation);
-        // print_monkeys(monkeys);
+    to_worry: impl Fn(i64) -> W,
+) -> Result<Vec<RoundSnapshot<W>>, SimulationError> {
+    let mut monkeys: Vec<Monkey<W>> = monkeys.iter().map(|m| m.with_worry(&to_worry)).collect();
+    let mut history = vec![snapshot(0, &monkeys, Vec::new())];
+
+    for round in 1..=rounds {
+        let mut throws = Vec::new();
+        inspection_round(&mut monkeys, intermediate_operation, Some(&mut throws)).map_err(
+            |(monkey, error)| SimulationError {
+                round,
+                monkey,
+                error,
+            },
+        )?;
+        history.push(snapshot(round, &monkeys, throws));
     }
+
+    Ok(history)
 }
 
-fn monkey_business_level(monkeys: &Vec<Monkey>) -> usize {
-    let mut items_inspected = monkeys
+// Moves a single item through one round. Items only depend on their own worry level,
+// and an item thrown to a monkey with a higher id is inspected again in the same round.
+// Returns the monkey holding the item afterwards, its worry level and the inspecting monkeys.
+fn item_round<W: Worry>(
+    monkeys: &[Monkey<W>],
+    mut holder: usize,
+    mut item: W,
+    intermediate_operation: &Operation,
+) -> Result<(usize, W, Vec<usize>), (usize, WorryError)> {
+    let mut inspected_by = Vec::new();
+
+    loop {
+        inspected_by.push(holder);
+        let (target, value) = inspect(&monkeys[holder], &item, intermediate_operation)
+            .map_err(|err| (holder, err))?;
+
+        let stays_in_round = target > holder;
+        holder = target;
+        item = value;
+
+        if !stays_in_round {
+            return Ok((holder, item, inspected_by));
+        }
+    }
+}
+
+// Inspections per monkey after `rounds` rounds. Every item is followed on its own until its
+// route (holder and worry level at the end of a round) repeats, and the rounds left are then
+// skipped in whole periods, like `longstep_until` in day17. Only terminates in reasonable
+// time for worry arithmetic with finitely many values, i.e. residues.
+fn fast_forward<W: Worry + Hash + Eq>(
+    monkeys: &[Monkey<W>],
+    rounds: usize,
+    intermediate_operation: &Operation,
+) -> Result<Vec<usize>, SimulationError> {
+    let mut items_inspected: Vec<usize> = monkeys.iter().map(|m| m.items_inspected).collect();
+
+    for (start_holder, start_item) in monkeys
         .iter()
-        .map(|m| m.items_inspected)
-        .collect::<Vec<_>>();
+        .flat_map(|m| m.items.iter().map(move |item| (m.id, item)))
+    {
+        // inspections per monkey accumulated until the start of each round
+        let mut totals: Vec<Vec<usize>> = vec![vec![0; monkeys.len()]];
+        let mut seen: HashMap<(usize, W), usize> = HashMap::new();
+
+        let mut state = (start_holder, start_item.clone());
+        let mut round = 0;
+
+        while round < rounds {
+            if let Some(&start) = seen.get(&state) {
+                let period = round - start;
+                let periods_left = (rounds - round) / period;
+                let rest = (rounds - round) % period;
+
+                for monkey in 0..monkeys.len() {
+                    let per_period = totals[round][monkey] - totals[start][monkey];
+                    let partial = totals[start + rest][monkey] - totals[start][monkey];
+                    items_inspected[monkey] +=
+                        totals[round][monkey] + per_period * periods_left + partial;
+                }
+                break;
+            }
+            seen.insert(state.clone(), round);
+
+            let (holder, item) = state;
+            let (holder, item, inspected_by) =
+                item_round(monkeys, holder, item, intermediate_operation).map_err(
+                    |(monkey, error)| SimulationError {
+                        round: round + 1,
+                        monkey,
+                        error,
+                    },
+                )?;
+
+            let mut total = totals[round].clone();
+            for monkey in inspected_by {
+                total[monkey] += 1;
+            }
+            totals.push(total);
+
+            state = (holder, item);
+            round += 1;
+        }
+
+        if round == rounds {
+            for (sum, count) in items_inspected.iter_mut().zip(&totals[rounds]) {
+                *sum += count;
+            }
+        }
+    }
+
+    Ok(items_inspected)
+}
+
+fn monkey_business(
+    monkeys: &[Monkey],
+    rounds: usize,
+    intermediate_operation: &Operation,
+    arithmetic: Arithmetic,
+) -> Result<usize, SimulationError> {
+    let op = intermediate_operation;
+
+    match arithmetic {
+        Arithmetic::Checked => inspection_rounds_impl(monkeys, rounds, op, |item| item)
+            .map(|m| monkey_business_level(&m)),
+        Arithmetic::Big => inspection_rounds_impl(monkeys, rounds, op, BigInt::from)
+            .map(|m| monkey_business_level(&m)),
+        Arithmetic::Residues | Arithmetic::Lcm => {
+            let moduli = match arithmetic {
+                Arithmetic::Lcm => vec![divisor_lcm(monkeys)?],
+                _ => divisors(monkeys),
+            };
+            let moduli = Rc::new(moduli);
+            let monkeys: Vec<Monkey<Residues>> = monkeys
+                .iter()
+                .map(|m| m.with_worry(|item| Residues::new(item, &moduli)))
+                .collect();
+
+            fast_forward(&monkeys, rounds, op).map(business_level)
+        }
+    }
+}
+
+fn monkey_business_level<W>(monkeys: &[Monkey<W>]) -> usize {
+    business_level(monkeys.iter().map(|m| m.items_inspected).collect())
+}
+
+fn business_level(mut items_inspected: Vec<usize>) -> usize {
     items_inspected.sort();
     items_inspected.reverse();
 
-    return items_inspected[0] * items_inspected[1];
+    items_inspected[0] * items_inspected[1]
 }
 
 fn gcd(a: i64, b: i64) -> i64 {
@@ -235,8 +1060,8 @@ fn gcd(a: i64, b: i64) -> i64 {
     return a;
 }
 
-fn lcm(a: i64, b: i64) -> i64 {
-    return a * b / gcd(a, b);
+fn lcm(a: i64, b: i64) -> Result<i64, WorryError> {
+    (a / gcd(a, b)).checked_mul(b).ok_or(WorryError::Overflow)
 }
 
 #[cfg(test)]
@@ -283,14 +1108,14 @@ Monkey 3:
         assert_eq!(first_monkey.items, vec![79, 98]);
         assert_eq!(first_monkey.operation.apply(1), 19);
         assert_eq!(first_monkey.operation.apply(2), 2 * 19);
-        assert_eq!(first_monkey.test.perform(23), true);
-        assert_eq!(first_monkey.test.perform(2), false);
-        assert_eq!(first_monkey.if_true, 2);
-        assert_eq!(first_monkey.if_false, 3);
+        assert_eq!(first_monkey.throws.rules, vec![(Test::DivisibleBy(23), 2)]);
+        assert!(first_monkey.throws.rules[0].0.perform(23));
+        assert!(!first_monkey.throws.rules[0].0.perform(2));
+        assert_eq!(first_monkey.throws.target(&23), Ok(2));
+        assert_eq!(first_monkey.throws.otherwise, 3);
 
         // run 20 rounds
-        let mut monkeys = parse_monkeys(INPUT);
-        inspection_rounds_part1(&mut monkeys, 20);
+        let monkeys = inspection_rounds_part1(&parse_monkeys(INPUT), 20);
         assert_eq!(monkeys[0].items_inspected, 101);
         assert_eq!(monkeys[1].items_inspected, 95);
         assert_eq!(monkeys[2].items_inspected, 7);
@@ -298,18 +1123,255 @@ Monkey 3:
 
         assert_eq!(monkey_business_level(&monkeys), 101 * 105);
 
-        assert_eq!(lcm(1, 1), 1);
-        assert_eq!(lcm(2, 4), 4);
-        assert_eq!(lcm(11, 13), 11 * 13);
+        assert_eq!(lcm(1, 1), Ok(1));
+        assert_eq!(lcm(2, 4), Ok(4));
+        assert_eq!(lcm(11, 13), Ok(11 * 13));
+        assert_eq!(lcm(i64::MAX, 2), Err(WorryError::Overflow));
+
+        // divisors whose common multiple does not fit an i64
+        let mut huge = parse_monkeys(INPUT);
+        huge[2].throws.rules[0].0 = Test::DivisibleBy(i64::MAX);
+        assert_eq!(
+            divisor_lcm(&huge),
+            Err(SimulationError {
+                round: 0,
+                monkey: 2,
+                error: WorryError::Overflow
+            })
+        );
+        assert_eq!(divisor_lcm(&parse_monkeys(INPUT)), Ok(23 * 19 * 13 * 17));
     }
 
     #[test]
     fn test_part2() {
         assert_eq!(10_000, 10000);
 
-        let mut monkeys = parse_monkeys(INPUT);
-        inspection_rounds_part2(&mut monkeys, 10_000);
+        let monkeys = inspection_rounds_part2(&parse_monkeys(INPUT), 10_000);
 
         assert_eq!(monkey_business_level(&monkeys), 2_713_310_158);
     }
+
+    #[test]
+    fn test_operation() {
+        let apply = |expression: &str, old| expression.parse::<Operation>().unwrap().apply(old);
+
+        assert_eq!(apply("old * 19", 2), 38);
+        assert_eq!(apply("old * old", 7), 49);
+        assert_eq!(apply("2 + old * 3", 4), 14);
+        assert_eq!(apply("(2 + old) * 3", 4), 18);
+        assert_eq!(apply("old - 2 - 3", 10), 5);
+        assert_eq!(apply("-old + 20 % 7 / 2", 1), 2);
+        assert_eq!(apply("old * (old - 1) / 2", 10), 45);
+
+        assert_eq!(
+            "old +".parse::<Operation>(),
+            Err("Unexpected end of expression".to_string())
+        );
+        assert_eq!("(old".parse::<Operation>(), Err("Missing ')'".to_string()));
+        assert_eq!(
+            "new * 2".parse::<Operation>(),
+            Err("Unknown variable 'new'".to_string())
+        );
+        assert_eq!(
+            "old 2".parse::<Operation>(),
+            Err("Unexpected Number(2)".to_string())
+        );
+        assert_eq!(
+            "old ^ 2".parse::<Operation>(),
+            Err("Unexpected character '^'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rules_dsl() {
+        let monkeys = parse_monkeys(
+            "Monkey 0:
+  Starting items: 5, 20
+  Operation: new = (old + 1) * 2
+  Test: greater than 30
+    If true: throw to monkey 1
+  Test: divisible by 3
+    If true: throw to monkey 2
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items:
+  Operation: new = old
+  Test: equal to 0
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 1
+  Operation: new = old - 1
+  Test: less than 0
+    If true: throw to monkey 1
+    If false: throw to monkey 0",
+        );
+
+        let throws = &monkeys[0].throws;
+        assert_eq!(throws.target(&42), Ok(1));
+        assert_eq!(throws.target(&12), Ok(2));
+        assert_eq!(throws.target(&13), Ok(1));
+        assert_eq!(monkeys[1].items, Vec::<i64>::new());
+        assert_eq!(monkeys[1].throws.rules, vec![(Test::EqualTo(0), 2)]);
+
+        let error = |input: &str| try_parse_monkeys(input).err().unwrap().to_string();
+
+        assert_eq!(
+            error("Monkey 0:\n  Starting items: 1\n  Operation: new = old ** 2"),
+            "line 3: Unexpected '*'"
+        );
+        assert_eq!(
+            error("Monkey 0:\n  Starting items: 1\n  Test: odd"),
+            "line 3: Unknown test 'odd'"
+        );
+        assert_eq!(
+            error("\n\nMonkey 0:\n  Starting items: 1, x"),
+            "line 4: Invalid item: invalid digit found in string"
+        );
+        assert_eq!(
+            error("Monkey 0:\n  Teleport: monkey 1"),
+            "line 2: Unmatched monkey definition line 'Teleport: monkey 1'"
+        );
+        assert_eq!(
+            error("Monkey 0:\n  Starting items: 1"),
+            "line 1: Monkey 0 has no operation"
+        );
+        assert_eq!(
+            error(&INPUT.replacen("Monkey 0:", "Monkey 1:", 1)),
+            "line 1: Expected monkey 0, got monkey 1"
+        );
+        assert_eq!(
+            error(&INPUT.replace("throw to monkey 3", "throw to monkey 4")),
+            "line 1: Monkey 0 throws to unknown monkey 4"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let monkeys = parse_monkeys(INPUT);
+        let relief = "old / 3".parse::<Operation>().unwrap();
+        let business =
+            |rounds, relief, arithmetic| monkey_business(&monkeys, rounds, relief, arithmetic);
+
+        assert_eq!(business(20, &relief, Arithmetic::Checked), Ok(101 * 105));
+        assert_eq!(business(20, &relief, Arithmetic::Big), Ok(101 * 105));
+        assert_eq!(
+            business(20, &relief, Arithmetic::Residues),
+            Err(SimulationError {
+                round: 1,
+                monkey: 0,
+                error: WorryError::Unsupported("division of residues")
+            })
+        );
+
+        let no_relief = Operation::Old;
+        assert_eq!(
+            business(1000, &no_relief, Arithmetic::Residues),
+            Ok(5204 * 5192)
+        );
+        assert_eq!(business(1000, &no_relief, Arithmetic::Lcm), Ok(5204 * 5192));
+        assert_eq!(
+            business(100, &no_relief, Arithmetic::Big),
+            business(100, &no_relief, Arithmetic::Lcm)
+        );
+        assert!(matches!(
+            business(20, &no_relief, Arithmetic::Checked),
+            Err(SimulationError {
+                error: WorryError::Overflow,
+                ..
+            })
+        ));
+
+        let moduli = Rc::new(vec![3, 5]);
+        let residues = Residues::new(7, &moduli)
+            .mul(&Residues::new(4, &moduli))
+            .unwrap();
+        assert_eq!(residues.values, vec![1, 3]);
+        assert_eq!(residues.test(&Test::DivisibleBy(5)), Ok(false));
+        assert_eq!(residues.neg().unwrap().values, vec![2, 2]);
+        assert_eq!(
+            residues.test(&Test::DivisibleBy(7)),
+            Err(WorryError::Unsupported("divisibility by a non-modulus"))
+        );
+        assert_eq!(
+            residues.test(&Test::GreaterThan(7)),
+            Err(WorryError::Unsupported("comparison of residues"))
+        );
+
+        assert_eq!(5i64.div(&0), Err(WorryError::DivisionByZero));
+        let by_minus_one: Test = "divisible by -1".parse().unwrap();
+        assert_eq!(i64::MIN.test(&by_minus_one), Err(WorryError::Overflow));
+        assert_eq!(7i64.test(&by_minus_one), Ok(true));
+        assert!(by_minus_one.perform(i64::MIN));
+        assert_eq!(i64::MAX.add(&1), Err(WorryError::Overflow));
+    }
+
+    #[test]
+    fn test_history() {
+        let monkeys = parse_monkeys(INPUT);
+        let relief = "old / 3".parse::<Operation>().unwrap();
+        let history = inspection_history(&monkeys, 20, &relief, |item| item).unwrap();
+
+        assert_eq!(history.len(), 21);
+        assert_eq!(history[0].items[0], vec![79, 98]);
+        assert!(history[0].throws.is_empty());
+
+        assert_eq!(history[1].items[0], vec![20, 23, 27, 26]);
+        assert_eq!(history[1].items[1], vec![2080, 25, 167, 207, 401, 1046]);
+        assert_eq!(history[1].items[2], vec![]);
+        assert_eq!(
+            history[1].throws[0],
+            Throw {
+                from: 0,
+                to: 3,
+                item: 500
+            }
+        );
+        assert_eq!(history[1].items_inspected, vec![2, 4, 3, 5]);
+        assert_eq!(history[20].items_inspected, vec![101, 95, 7, 105]);
+        assert_eq!(history[20].items[0], vec![10, 12, 14, 26, 34]);
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let monkeys = parse_monkeys(INPUT);
+        let no_relief = Operation::Old;
+
+        assert_eq!(
+            monkey_business(&monkeys, 10_000, &no_relief, Arithmetic::Residues),
+            Ok(2_713_310_158)
+        );
+        assert_eq!(
+            monkey_business(&monkeys, 10_000, &no_relief, Arithmetic::Lcm),
+            Ok(2_713_310_158)
+        );
+
+        let relief = "old / 3".parse::<Operation>().unwrap();
+        let counts = fast_forward(&monkeys, 20, &relief).unwrap();
+        assert_eq!(counts, vec![101, 95, 7, 105]);
+
+        let moduli = Rc::new(divisors(&monkeys));
+        let residues: Vec<Monkey<Residues>> = monkeys
+            .iter()
+            .map(|m| m.with_worry(|item| Residues::new(item, &moduli)))
+            .collect();
+        for rounds in [0, 1, 20, 1000, 5000] {
+            let simulated = inspection_rounds_impl(&monkeys, rounds, &no_relief, |item| {
+                Residues::new(item, &moduli)
+            })
+            .unwrap();
+            let simulated: Vec<usize> = simulated.iter().map(|m| m.items_inspected).collect();
+            assert_eq!(
+                fast_forward(&residues, rounds, &no_relief).unwrap(),
+                simulated
+            );
+        }
+
+        assert_eq!(
+            monkey_business(&monkeys, 100_000_000, &no_relief, Arithmetic::Residues),
+            monkey_business(&monkeys, 100_000_000, &no_relief, Arithmetic::Lcm)
+        );
+    }
 }
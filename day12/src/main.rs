@@ -1,26 +1,56 @@
 use std::collections::VecDeque;
-use std::fs;
+use std::{env, fs};
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
 
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
+
+    // `cargo run -- route` also draws both routes on the heightmap
+    if env::args().nth(1).as_deref() == Some("route") {
+        let map = Map::from_str(&input);
+        for route in [map.ascent_route(), map.descent_route()].iter().flatten() {
+            println!("\n{}", map.render_route(route, None));
+        }
+    }
+
+    // `cargo run -- field <steps> [max climb] [max drop]` summarizes the distances to 'E'
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 && args[1] == "field" {
+        let steps = args[2].parse::<usize>().unwrap();
+        let mut rule = ClimbRule::default();
+        if let Some(max_climb) = args.get(3) {
+            rule.max_climb = max_climb.parse().unwrap();
+        }
+        if let Some(max_drop) = args.get(4) {
+            rule.max_drop = max_drop.parse().unwrap();
+        }
+
+        let map = Map::with_rule(&input, rule);
+        println!(
+            "Starts within {} steps: {}",
+            steps,
+            map.starts_within(steps).len()
+        );
+        println!("Squares that can't reach E: {}", map.unreachable().len());
+    }
 }
 
 fn part1(input: &str) -> usize {
     let map = Map::from_str(input);
-    map.ascend()
+    map.ascend().expect("No path found")
 }
 
 fn part2(input: &str) -> usize {
     let map = Map::from_str(input);
-    map.descend()
+    map.descend().expect("No path found")
 }
 
 struct Map {
     grid: Vec<Vec<char>>,
     size: (usize, usize),
+    rule: ClimbRule,
 }
 
 type Pos = (usize, usize);
@@ -33,15 +63,44 @@ fn height_char_to_u8(c: char) -> u8 {
     }
 }
 
-fn is_valid_ascent(from: char, to: char) -> bool {
-    let from_u8 = height_char_to_u8(from);
-    let to_u8 = height_char_to_u8(to);
+// How far a single step may go up or down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ClimbRule {
+    max_climb: u8,
+    max_drop: u8,
+}
+
+impl ClimbRule {
+    fn allows(&self, from: char, to: char) -> bool {
+        let from_u8 = height_char_to_u8(from);
+        let to_u8 = height_char_to_u8(to);
+
+        to_u8 <= from_u8.saturating_add(self.max_climb)
+            && from_u8 <= to_u8.saturating_add(self.max_drop)
+    }
+}
 
-    return from_u8 + 1 >= to_u8;
+// at most one higher, any lower
+impl Default for ClimbRule {
+    fn default() -> ClimbRule {
+        ClimbRule {
+            max_climb: 1,
+            max_drop: u8::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+fn is_valid_ascent(from: char, to: char) -> bool {
+    ClimbRule::default().allows(from, to)
 }
 
 impl Map {
     fn from_str(input: &str) -> Map {
+        Map::with_rule(input, ClimbRule::default())
+    }
+
+    fn with_rule(input: &str, rule: ClimbRule) -> Map {
         let grid = input
             .lines()
             .map(|line| line.chars().collect())
@@ -52,7 +111,7 @@ impl Map {
         // assert that all lines are the same length
         assert!(grid.iter().all(|line| line.len() == size.1));
 
-        Map { grid, size }
+        Map { grid, size, rule }
     }
 
     fn init_grid<T: Copy>(&self, value: T) -> Vec<Vec<T>> {
@@ -106,79 +165,165 @@ impl Map {
         None
     }
 
-    fn ascend(&self) -> usize {
-        let start = self.find('S').unwrap();
-        let end = self.find('E').unwrap();
-
+    // Breadth-first search from `start` to the closest position that satisfies `is_goal`,
+    // only taking steps for which `can_step(from, to)` holds. Returns the whole route.
+    fn shortest_route(
+        &self,
+        start: Pos,
+        is_goal: impl Fn(Pos) -> bool,
+        can_step: impl Fn(char, char) -> bool,
+    ) -> Option<Vec<Pos>> {
+        let mut previous: Vec<Vec<Option<Pos>>> = self.init_grid(None);
         let mut open = self.init_grid(true);
 
-        let mut queue: VecDeque<(Pos, usize)> = VecDeque::new();
-        queue.push_back((start, 0));
+        let mut queue: VecDeque<Pos> = VecDeque::new();
+        queue.push_back(start);
         open[start.0][start.1] = false;
 
-        while !queue.is_empty() {
-            let (pos, current_distance) = queue.pop_front().unwrap();
-
-            assert!(!open[pos.0][pos.1]);
-
-            if pos == end {
-                return current_distance;
+        while let Some(pos) = queue.pop_front() {
+            if is_goal(pos) {
+                let mut route = vec![pos];
+                while let Some(prev) = previous[route.last().unwrap().0][route.last().unwrap().1] {
+                    route.push(prev);
+                }
+                route.reverse();
+                return Some(route);
             }
 
-            let next_distance = current_distance + 1;
-
             let from_height = self.get(pos).unwrap();
 
-            let neighbors = self.get_neighbors(pos);
-            for neighbor in neighbors {
+            for neighbor in self.get_neighbors(pos) {
                 let to_height = self.get(neighbor).unwrap();
 
-                if open[neighbor.0][neighbor.1] && is_valid_ascent(from_height, to_height) {
-                    queue.push_back((neighbor, next_distance));
+                if open[neighbor.0][neighbor.1] && can_step(from_height, to_height) {
+                    queue.push_back(neighbor);
                     open[neighbor.0][neighbor.1] = false;
+                    previous[neighbor.0][neighbor.1] = Some(pos);
                 }
             }
         }
 
-        panic!("No path found");
+        None
     }
 
-    fn descend(&self) -> usize {
-        let end = self.find('E').unwrap();
-
-        let mut open = self.init_grid(true);
-
-        let mut queue: VecDeque<(Pos, usize)> = VecDeque::new();
-        queue.push_back((end, 0));
-        open[end.0][end.1] = false;
+    // shortest route from 'S' to 'E'
+    fn ascent_route(&self) -> Option<Vec<Pos>> {
+        let start = self.find('S')?;
+        let end = self.find('E')?;
 
-        while !queue.is_empty() {
-            let (pos, current_distance) = queue.pop_front().unwrap();
+        self.shortest_route(
+            start,
+            |pos| pos == end,
+            |from, to| self.rule.allows(from, to),
+        )
+    }
 
-            assert!(!open[pos.0][pos.1]);
+    // shortest route from any lowest square ('S' or 'a') to 'E'
+    fn descent_route(&self) -> Option<Vec<Pos>> {
+        let end = self.find('E')?;
+        let is_lowest = |pos| matches!(self.get(pos), Some('S') | Some('a'));
 
-            match self.get(pos) {
-                Some('S') => return current_distance,
-                Some('a') => return current_distance,
-                Some(_) => {}
-                None => panic!("Invalid position during descent"),
-            }
+        // search downwards from the end, so the steps are checked in reverse
+        let mut route =
+            self.shortest_route(end, is_lowest, |from, to| self.rule.allows(to, from))?;
+        route.reverse();
+        Some(route)
+    }
 
-            let next_distance = current_distance + 1;
+    // Number of steps from every square to 'E', or `None` where 'E' can't be reached.
+    // A single breadth-first search from 'E' that follows the climb rule backwards.
+    fn distances_to_end(&self) -> Vec<Vec<Option<usize>>> {
+        let mut distances = self.init_grid(None);
+        let end = match self.find('E') {
+            Some(end) => end,
+            None => return distances,
+        };
+
+        let mut queue: VecDeque<Pos> = VecDeque::new();
+        queue.push_back(end);
+        distances[end.0][end.1] = Some(0);
+
+        while let Some(pos) = queue.pop_front() {
+            let next_distance = distances[pos.0][pos.1].map(|distance| distance + 1);
             let to_height = self.get(pos).unwrap();
 
-            let neighbors = self.get_neighbors(pos);
-            for neighbor in neighbors {
+            for neighbor in self.get_neighbors(pos) {
                 let from_height = self.get(neighbor).unwrap();
 
-                if open[neighbor.0][neighbor.1] && is_valid_ascent(from_height, to_height) {
-                    queue.push_back((neighbor, next_distance));
-                    open[neighbor.0][neighbor.1] = false;
+                if distances[neighbor.0][neighbor.1].is_none()
+                    && self.rule.allows(from_height, to_height)
+                {
+                    distances[neighbor.0][neighbor.1] = next_distance;
+                    queue.push_back(neighbor);
                 }
             }
         }
 
-        panic!("No path found");
+        distances
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Pos> + '_ {
+        (0..self.size.0).flat_map(move |i| (0..self.size.1).map(move |j| (i, j)))
+    }
+
+    // lowest squares ('S' or 'a') from which 'E' is at most `steps` steps away
+    fn starts_within(&self, steps: usize) -> Vec<Pos> {
+        let distances = self.distances_to_end();
+
+        self.positions()
+            .filter(|&pos| matches!(self.get(pos), Some('S') | Some('a')))
+            .filter(|&(i, j)| distances[i][j].is_some_and(|distance| distance <= steps))
+            .collect()
+    }
+
+    // squares from which 'E' can't be reached at all
+    fn unreachable(&self) -> Vec<Pos> {
+        let distances = self.distances_to_end();
+
+        self.positions()
+            .filter(|&(i, j)| distances[i][j].is_none())
+            .collect()
+    }
+
+    fn ascend(&self) -> Option<usize> {
+        self.ascent_route().map(|route| route.len() - 1)
+    }
+
+    fn descend(&self) -> Option<usize> {
+        self.descent_route().map(|route| route.len() - 1)
+    }
+
+    // The heightmap with every step of the route drawn as an arrow towards the next
+    // position. Squares that are not part of the route are shown as `background`,
+    // or with their height if there is none.
+    fn render_route(&self, route: &[Pos], background: Option<char>) -> String {
+        let mut grid = self.grid.clone();
+        if let Some(background) = background {
+            for line in grid.iter_mut() {
+                line.fill(background);
+            }
+            if let Some(&last) = route.last() {
+                grid[last.0][last.1] = self.grid[last.0][last.1];
+            }
+        }
+
+        for step in route.windows(2) {
+            let (from, to) = (step[0], step[1]);
+            grid[from.0][from.1] = if to.0 < from.0 {
+                '^'
+            } else if to.0 > from.0 {
+                'v'
+            } else if to.1 < from.1 {
+                '<'
+            } else {
+                '>'
+            };
+        }
+
+        grid.iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
@@ -220,12 +365,124 @@ abdefghi";
         assert_eq!(map.find('S'), Some((0, 0)));
         assert_eq!(map.find('E'), Some((2, 5)));
 
-        assert_eq!(map.ascend(), 31);
+        assert_eq!(map.ascend(), Some(31));
     }
 
     #[test]
     fn test_part2() {
         let map = Map::from_str(INPUT);
-        assert_eq!(map.descend(), 29);
+        assert_eq!(map.descend(), Some(29));
+    }
+
+    #[test]
+    fn test_route() {
+        let map = Map::from_str(INPUT);
+
+        let route = map.ascent_route().unwrap();
+        assert_eq!(route.len(), 32);
+        assert_eq!(route.first(), Some(&(0, 0)));
+        assert_eq!(route.last(), Some(&(2, 5)));
+        for step in route.windows(2) {
+            assert!(map.get_neighbors(step[0]).contains(&step[1]));
+            assert!(is_valid_ascent(
+                map.get(step[0]).unwrap(),
+                map.get(step[1]).unwrap()
+            ));
+        }
+
+        let route = map.descent_route().unwrap();
+        assert_eq!(route.len(), 30);
+        assert_eq!(map.get(route[0]), Some('a'));
+        assert_eq!(route.last(), Some(&(2, 5)));
+
+        let map = Map::from_str("Sbcdefghijklm\nEyxwvutsrqpon\naaaaaaaaaaaaa");
+        let route = map.ascent_route().unwrap();
+        assert_eq!(
+            map.render_route(&route, Some('.')),
+            ">>>>>>>>>>>>v\nE<<<<<<<<<<<<\n............."
+        );
+        assert_eq!(
+            map.render_route(&route, None),
+            ">>>>>>>>>>>>v\nE<<<<<<<<<<<<\naaaaaaaaaaaaa"
+        );
+
+        let map = Map::from_str("Sac\naaE");
+        assert_eq!(map.ascent_route(), None);
+        assert_eq!(map.ascend(), None);
+        assert_eq!(map.descend(), None);
+    }
+
+    #[test]
+    fn test_descent_rule() {
+        let map = Map::from_str(INPUT);
+        assert_eq!(map.descend(), Some(29));
+
+        // the descent follows the configured rule, like the ascent and the distance field
+        let rule = ClimbRule {
+            max_climb: 2,
+            max_drop: u8::MAX,
+        };
+        let map = Map::with_rule(INPUT, rule);
+        let route = map.descent_route().unwrap();
+        assert_eq!(route.len() - 1, 23);
+        assert!(route
+            .windows(2)
+            .all(|step| rule.allows(map.get(step[0]).unwrap(), map.get(step[1]).unwrap())));
+        assert!(route
+            .windows(2)
+            .any(|step| !is_valid_ascent(map.get(step[0]).unwrap(), map.get(step[1]).unwrap())));
+        assert_eq!(
+            map.descend(),
+            map.starts_within(usize::MAX)
+                .iter()
+                .map(|&(i, j)| map.distances_to_end()[i][j].unwrap())
+                .min()
+        );
+    }
+
+    #[test]
+    fn test_distance_field() {
+        let map = Map::from_str(INPUT);
+        let distances = map.distances_to_end();
+
+        assert_eq!(distances[2][5], Some(0));
+        assert_eq!(distances[0][0], Some(31));
+        assert_eq!(distances[4][0], Some(29));
+        assert!(map.unreachable().is_empty());
+
+        // part 2 is the closest lowest square
+        let closest = map
+            .starts_within(usize::MAX)
+            .iter()
+            .map(|&(i, j)| distances[i][j].unwrap())
+            .min();
+        assert_eq!(closest, map.descend());
+        assert_eq!(map.starts_within(28), vec![]);
+        assert_eq!(map.starts_within(29), vec![(4, 0)]);
+        assert_eq!(map.starts_within(30), vec![(0, 1), (1, 0), (3, 0), (4, 0)]);
+
+        // climbing two at a time allows shortcuts
+        let rule = ClimbRule {
+            max_climb: 2,
+            max_drop: u8::MAX,
+        };
+        let map = Map::with_rule(INPUT, rule);
+        assert!(map.ascend().unwrap() < 31);
+
+        // a valley on the way that can't be left without dropping down first
+        let input = "SbcbcdefghijklmnopqrstuvwxyE";
+        let rule = ClimbRule {
+            max_climb: 1,
+            max_drop: 0,
+        };
+        let map = Map::with_rule(input, rule);
+        assert_eq!(map.unreachable(), vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(map.distances_to_end()[0][3], Some(24));
+        assert_eq!(map.ascend(), None);
+
+        let map = Map::from_str(input);
+        assert_eq!(map.unreachable(), vec![]);
+        assert_eq!(map.distances_to_end()[0][0], Some(27));
+        assert_eq!(map.ascend(), Some(27));
     }
 }
@@ -1,8 +1,38 @@
-use std::{cmp::Ordering, fs};
+use serde_json::Value;
+use std::{cmp::Ordering, env, fmt, fs, iter::Peekable, slice};
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("sorted") {
+        // read packets from any JSON producer and print them in order as one JSON array
+        let path = args.get(2).map_or("input.txt", String::as_str);
+        let mut items: Vec<Item> = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Item::from_json(line).unwrap_or_else(|error| panic!("{}: {}", error, line)))
+            .collect();
+        items.sort();
+        let sorted = Value::Array(items.iter().map(Value::from).collect());
+        println!("{}", sorted);
+        return;
+    }
+
     let input = fs::read_to_string("input.txt").unwrap();
 
+    if args.get(1).map(String::as_str) == Some("explain") {
+        let pairs = split_to_vec_of_pairs(&input);
+        match args.get(2) {
+            Some(pair) => {
+                let index: usize = pair.parse().expect("Pair numbers start at 1");
+                let (left, right) = &pairs[index - 1];
+                println!("== Pair {} ==\n{}", index, Trace::new(left, right));
+            }
+            None => println!("{}", explain_pairs(&pairs)),
+        }
+        return;
+    }
+
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
 }
@@ -24,92 +54,277 @@ enum Item {
 }
 
 impl Item {
-    fn is_right_order(&self, item: &Item) -> (bool, Vec<String>) {
-        #[cfg(test)]
-        println!("Comparing {:?} and {:?}", self, item);
-
-        match (self, item) {
-            (Item::Number(left), Item::Number(right)) => {
-                if left <= right {
-                    (true, vec![])
-                } else {
-                    (false, vec![format!("{} > {}", left, right)])
-                }
+    // A pair is in the right order only if the left packet sorts strictly
+    // before the right one; equal packets leave the question undecided.
+    fn is_right_order(&self, item: &Item) -> bool {
+        self.cmp(item) == Ordering::Less
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, Item::Number(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+// The rule that settled a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    LeftSmaller,
+    RightSmaller,
+    LeftRanOut,
+    RightRanOut,
+}
+
+impl Rule {
+    fn ordering(self) -> Ordering {
+        match self {
+            Rule::LeftSmaller | Rule::LeftRanOut => Ordering::Less,
+            Rule::RightSmaller | Rule::RightRanOut => Ordering::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StepKind {
+    Compare(Item, Item),
+    // An integer on the given side was wrapped into the list shown.
+    Promote(Side, Item),
+    Decide(Rule),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    depth: usize,
+    kind: StepKind,
+}
+
+// Every step taken while comparing two packets, in the order of the puzzle's
+// walkthrough. `result` is always the same as `Ord::cmp` on the packets.
+#[derive(Debug, Clone, PartialEq)]
+struct Trace {
+    steps: Vec<Step>,
+    result: Ordering,
+}
+
+impl Trace {
+    fn new(left: &Item, right: &Item) -> Trace {
+        let mut steps = Vec::new();
+        let result = trace_compare(left, right, 0, &mut steps);
+
+        Trace { steps, result }
+    }
+
+    #[cfg(test)]
+    fn rule(&self) -> Option<Rule> {
+        self.steps.iter().find_map(|step| match step.kind {
+            StepKind::Decide(rule) => Some(rule),
+            _ => None,
+        })
+    }
+}
+
+fn trace_compare(left: &Item, right: &Item, depth: usize, steps: &mut Vec<Step>) -> Ordering {
+    steps.push(Step {
+        depth,
+        kind: StepKind::Compare(left.clone(), right.clone()),
+    });
+    let decide = |steps: &mut Vec<Step>, rule: Rule| {
+        steps.push(Step {
+            depth: depth + 1,
+            kind: StepKind::Decide(rule),
+        });
+        rule.ordering()
+    };
+
+    match (left, right) {
+        (Item::Number(l), Item::Number(r)) => match l.cmp(r) {
+            Ordering::Less => decide(steps, Rule::LeftSmaller),
+            Ordering::Greater => decide(steps, Rule::RightSmaller),
+            Ordering::Equal => Ordering::Equal,
+        },
+        (Item::Number(number), Item::List(_)) | (Item::List(_), Item::Number(number)) => {
+            let side = if left.is_number() {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            let promoted = Item::List(vec![Item::Number(*number)]);
+            steps.push(Step {
+                depth: depth + 1,
+                kind: StepKind::Promote(side, promoted.clone()),
+            });
+            match side {
+                Side::Left => trace_compare(&promoted, right, depth + 1, steps),
+                Side::Right => trace_compare(left, &promoted, depth + 1, steps),
             }
-            (Item::Number(left), Item::List(_)) => ln(*left).is_right_order(item),
-            (Item::List(_), Item::Number(right)) => self.is_right_order(&ln(*right)),
-            (Item::List(left), Item::List(right)) => {
-                if left.is_empty() {
-                    #[cfg(test)]
-                    println!("left is empty");
-                    return (true, vec![]);
+        }
+        (Item::List(l), Item::List(r)) => {
+            for (left, right) in l.iter().zip(r) {
+                match trace_compare(left, right, depth + 1, steps) {
+                    Ordering::Equal => continue,
+                    decided => return decided,
                 }
+            }
 
-                if right.is_empty() {
-                    #[cfg(test)]
-                    println!("right is empty");
-                    return (false, vec![]);
-                }
+            match l.len().cmp(&r.len()) {
+                Ordering::Less => decide(steps, Rule::LeftRanOut),
+                Ordering::Greater => decide(steps, Rule::RightRanOut),
+                Ordering::Equal => Ordering::Equal,
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}- ", "  ".repeat(self.depth))?;
+
+        match &self.kind {
+            StepKind::Compare(left, right) => write!(f, "Compare {} vs {}", left, right),
+            StepKind::Promote(side, promoted) => {
+                let side = match side {
+                    Side::Left => "left",
+                    Side::Right => "right",
+                };
+                write!(
+                    f,
+                    "Mixed types; convert {} to {} and retry comparison",
+                    side, promoted
+                )
+            }
+            StepKind::Decide(rule) => {
+                let reason = match rule {
+                    Rule::LeftSmaller => "Left side is smaller",
+                    Rule::RightSmaller => "Right side is smaller",
+                    Rule::LeftRanOut => "Left side ran out of items",
+                    Rule::RightRanOut => "Right side ran out of items",
+                };
+                let verdict = match rule.ordering() {
+                    Ordering::Less => "in the right order",
+                    _ => "not in the right order",
+                };
+                write!(f, "{}, so inputs are {}", reason, verdict)
+            }
+        }
+    }
+}
 
-                let left_front = &left[0];
-                let left_rest = l(left[1..].to_vec());
-                let right_front = &right[0];
-                let right_rest = l(right[1..].to_vec());
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", step)?;
+        }
 
-                let left_is_right_order = left_front.is_right_order(right_front).0;
-                let right_is_right_order = right_front.is_right_order(left_front).0;
+        Ok(())
+    }
+}
 
-                match (left_is_right_order, right_is_right_order) {
-                    (true, true) => { // both are equal, continue
-                    }
-                    (true, false) => {
-                        // left is smaller than right, return true
-                        return (true, vec![]);
-                    }
-                    (false, true) => {
-                        // left is larger than right, return false
-                        return (false, vec![]);
-                    }
-                    (false, false) => {
-                        panic!("Logical error: both list fronts are not in order in any way");
+fn explain_pairs(pairs: &[(Item, Item)]) -> String {
+    pairs
+        .iter()
+        .enumerate()
+        .map(|(i, (left, right))| format!("== Pair {} ==\n{}", i + 1, Trace::new(left, right)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// Writes the packet back in the compact input format, e.g. `[1,[2,3],[]]`.
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Number(number) => write!(f, "{}", number),
+            Item::List(list) => {
+                write!(f, "[")?;
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
                     }
+                    write!(f, "{}", item)?;
                 }
-
-                // convert left_rest to Vec<Item>
-                left_rest.is_right_order(&right_rest)
+                write!(f, "]")
             }
         }
     }
 }
 
-impl PartialEq for Item {
-    fn eq(&self, other: &Self) -> bool {
-        self.is_right_order(other).0 && other.is_right_order(self).0
+impl From<&Item> for Value {
+    fn from(item: &Item) -> Self {
+        match item {
+            Item::Number(number) => Value::from(*number),
+            Item::List(list) => Value::Array(list.iter().map(Value::from).collect()),
+        }
     }
 }
 
-impl PartialOrd for Item {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let left_is_right = self.is_right_order(other).0;
-        let right_is_right = other.is_right_order(self).0;
-
-        match (left_is_right, right_is_right) {
-            (true, true) => Some(Ordering::Equal),
-            (true, false) => Some(Ordering::Less),
-            (false, true) => Some(Ordering::Greater),
-            _ => None,
+impl TryFrom<&Value> for Item {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) => number
+                .as_i64()
+                .and_then(|number| i32::try_from(number).ok())
+                .map(Item::Number)
+                .ok_or_else(|| format!("Number {} is not a 32-bit integer", number)),
+            Value::Array(list) => list
+                .iter()
+                .map(Item::try_from)
+                .collect::<Result<_, _>>()
+                .map(Item::List),
+            _ => Err(format!(
+                "Packets only hold integers and lists, found {}",
+                value
+            )),
         }
     }
 }
 
-impl Eq for Item {}
+fn compare_lists(left: &[Item], right: &[Item]) -> Ordering {
+    for (left, right) in left.iter().zip(right) {
+        match left.cmp(right) {
+            Ordering::Equal => continue,
+            decided => return decided,
+        }
+    }
+
+    // one side ran out of items (or both did, which makes the lists equal)
+    left.len().cmp(&right.len())
+}
 
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        match (self, other) {
+            (Item::Number(left), Item::Number(right)) => left.cmp(right),
+            (Item::Number(_), Item::List(right)) => compare_lists(slice::from_ref(self), right),
+            (Item::List(left), Item::Number(_)) => compare_lists(left, slice::from_ref(other)),
+            (Item::List(left), Item::List(right)) => compare_lists(left, right),
+        }
     }
 }
 
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Packets are equal when neither sorts before the other, so `1 == [1]`.
+impl PartialEq for Item {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Item {}
+
 fn index_sum_of_rightly_ordered_pairs_from_str(input: &str) -> usize {
     let pairs = split_to_vec_of_pairs(input);
 
@@ -127,7 +342,7 @@ fn index_sum_of_rightly_ordered_pairs_from_str(input: &str) -> usize {
 }
 
 fn get_rightly_ordered_pairs(pairs: &[(Item, Item)]) -> Vec<bool> {
-    pairs.iter().map(|(a, b)| a.is_right_order(b).0).collect()
+    pairs.iter().map(|(a, b)| a.is_right_order(b)).collect()
 }
 
 fn split_to_vec_of_pairs(input: &str) -> Vec<(Item, Item)> {
@@ -148,110 +363,138 @@ fn n(number: i32) -> Item {
     Item::Number(number)
 }
 
+#[cfg(test)]
 fn l(list: Vec<Item>) -> Item {
     Item::List(list)
 }
 
+#[cfg(test)]
 fn ln(number: i32) -> Item {
     Item::List(vec![Item::Number(number)])
 }
 
-enum Token {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
     OpenBracket,
     CloseBracket,
-    Number(i32),
+    Comma,
+    Number(&'a str),
 }
 
-impl Item {
-    #[allow(dead_code)]
-    fn from_str(input: &str) -> Item {
-        let mut tokens: Vec<Token> = Vec::new();
+// Splits a packet into tokens that borrow their digits from the input.
+struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(input: &'a str) -> Self {
+        Tokenizer { input, position: 0 }
+    }
+}
 
-        let mut continued_token = String::new();
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token<'a>, String>;
 
-        for char in input.trim().chars() {
-            match char {
-                '[' => {
-                    if !continued_token.is_empty() {
-                        tokens.push(Token::Number(continued_token.parse().unwrap()));
-                        continued_token = String::new();
-                    }
-                    tokens.push(Token::OpenBracket);
-                }
-                ']' => {
-                    if !continued_token.is_empty() {
-                        tokens.push(Token::Number(continued_token.parse().unwrap()));
-                        continued_token = String::new();
-                    }
-                    tokens.push(Token::CloseBracket);
-                }
-                ',' => {
-                    if !continued_token.is_empty() {
-                        tokens.push(Token::Number(continued_token.parse().unwrap()));
-                        continued_token = String::new();
-                    }
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.input.as_bytes();
+        while bytes
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+        let start = self.position;
+        let byte = *bytes.get(start)?;
+
+        let token = match byte {
+            b'[' => Token::OpenBracket,
+            b']' => Token::CloseBracket,
+            b',' => Token::Comma,
+            b'-' | b'0'..=b'9' => {
+                let digits = start + usize::from(byte == b'-');
+                let end = bytes[digits..]
+                    .iter()
+                    .position(|b| !b.is_ascii_digit())
+                    .map_or(bytes.len(), |length| digits + length);
+                self.position = end;
+                if end == digits {
+                    return Some(Err(format!("Missing digits after '-' at offset {}", start)));
                 }
-                '0'..='9' => continued_token.push(char),
-                _ => panic!("Invalid character: {}", char),
+                return Some(Ok(Token::Number(&self.input[start..end])));
             }
-        }
-
-        if !continued_token.is_empty() {
-            tokens.push(Token::Number(continued_token.parse().unwrap()));
-        }
+            _ => {
+                // skip the whole character so the error shows it intact
+                let char = self.input[start..].chars().next().unwrap();
+                self.position = bytes.len();
+                return Some(Err(format!(
+                    "Invalid character {:?} at offset {}",
+                    char, start
+                )));
+            }
+        };
 
-        let opening_bracket_count = tokens
-            .iter()
-            .filter(|t| matches!(t, Token::OpenBracket))
-            .count();
-        let closing_bracket_count = tokens
-            .iter()
-            .filter(|t| matches!(t, Token::CloseBracket))
-            .count();
+        self.position += 1;
+        Some(Ok(token))
+    }
+}
 
-        assert_eq!(opening_bracket_count, closing_bracket_count);
+type Tokens<'a> = Peekable<Tokenizer<'a>>;
 
-        assert!(!tokens.is_empty());
+fn next_token<'a>(tokens: &mut Tokens<'a>) -> Result<Token<'a>, String> {
+    tokens
+        .next()
+        .unwrap_or_else(|| Err("Unexpected end of packet".to_string()))
+}
 
-        Item::from_tokens(tokens)
+impl Item {
+    fn from_str(input: &str) -> Item {
+        Item::parse(input).unwrap_or_else(|error| panic!("{}: {}", error, input))
     }
 
-    fn from_tokens(tokens: Vec<Token>) -> Item {
-        let mut item_stack: Vec<Item> = Vec::new();
+    fn parse(input: &str) -> Result<Item, String> {
+        let mut tokens = Tokenizer::new(input.trim()).peekable();
+        let item = Item::parse_item(&mut tokens)?;
 
-        if tokens.len() == 1 {
-            match tokens[0] {
-                Token::Number(number) => return Item::Number(number),
-                _ => panic!("Invalid solitary token, which is not a number"),
-            }
+        match tokens.next() {
+            None => Ok(item),
+            Some(Ok(token)) => Err(format!("Unexpected {:?} after end of packet", token)),
+            Some(Err(error)) => Err(error),
         }
+    }
 
-        for token in tokens {
-            match token {
-                Token::OpenBracket => {
-                    item_stack.push(Item::List(Vec::new()));
+    // Reads a packet through serde_json, so any valid JSON layout is accepted.
+    fn from_json(input: &str) -> Result<Item, String> {
+        let value: Value = serde_json::from_str(input).map_err(|error| error.to_string())?;
+        Item::try_from(&value)
+    }
+
+    fn parse_item(tokens: &mut Tokens) -> Result<Item, String> {
+        match next_token(tokens)? {
+            Token::Number(digits) => digits
+                .parse()
+                .map(Item::Number)
+                .map_err(|error| format!("Invalid number {}: {}", digits, error)),
+            Token::OpenBracket => {
+                let mut list = Vec::new();
+
+                if tokens.peek() == Some(&Ok(Token::CloseBracket)) {
+                    tokens.next();
+                    return Ok(Item::List(list));
                 }
-                Token::Number(number) => match item_stack.last_mut() {
-                    Some(Item::List(list)) => {
-                        list.push(Item::Number(number));
-                    }
-                    _ => panic!("Unexpected number token, which is not in a list"),
-                },
-                Token::CloseBracket => {
-                    // push the current top item onto the one before it
-                    let item = item_stack.pop().unwrap();
-                    match item_stack.last_mut() {
-                        Some(Item::List(list)) => {
-                            list.push(item);
-                        }
-                        None => return item,
-                        _ => panic!("Unexpected closing bracket, which is not in a list"),
+
+                loop {
+                    list.push(Item::parse_item(tokens)?);
+
+                    match next_token(tokens)? {
+                        Token::Comma => continue,
+                        Token::CloseBracket => return Ok(Item::List(list)),
+                        token => return Err(format!("Expected ',' or ']', found {:?}", token)),
                     }
                 }
             }
+            token => Err(format!("Expected a number or '[', found {:?}", token)),
         }
-
-        panic!("Unexpected end of tokens without final closing bracket, maybe");
     }
 }
 
@@ -331,7 +574,7 @@ mod tests {
         assert_eq!(Item::from_str("9"), Item::Number(9));
         assert_eq!(Item::from_str("[8]"), Item::List(vec![Item::Number(8)]));
 
-        assert_eq!(ln(9).is_right_order(&ln(8)).0, false);
+        assert_eq!(ln(9).is_right_order(&ln(8)), false);
         assert!(ln(9) > ln(8));
 
         assert!(Item::from_str("9") > Item::from_str("[8]"));
@@ -352,6 +595,96 @@ mod tests {
         assert_eq!(index_sum_of_rightly_ordered_pairs_from_str(INPUT), 13);
     }
 
+    #[test]
+    fn test_equal_packets() {
+        let packet = Item::from_str("[[1],[2,3,4]]");
+
+        assert_eq!(packet.cmp(&packet.clone()), Ordering::Equal);
+        assert!(!packet.is_right_order(&packet));
+        assert_eq!(
+            Item::from_str("[[1]]").cmp(&Item::from_str("1")),
+            Ordering::Equal
+        );
+
+        // a long shared prefix is compared in place, element by element
+        let long = format!("[{}]", vec!["7"; 10_000].join(","));
+        let longer = format!("[{},8]", vec!["7"; 10_000].join(","));
+        assert!(Item::from_str(&long).is_right_order(&Item::from_str(&longer)));
+
+        assert_eq!(Item::parse("[10,[]]"), Ok(l(vec![n(10), l(vec![])])));
+        assert!(Item::parse("[1,,2]").is_err());
+        assert!(Item::parse("[1]]").is_err());
+        assert!(Item::parse("[1").is_err());
+        assert!(Item::parse("[a]").is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        for line in INPUT.lines().filter(|line| !line.is_empty()) {
+            let item = Item::from_str(line);
+            assert_eq!(item.to_string(), line);
+            assert_eq!(Value::from(&item).to_string(), line);
+            assert_eq!(Item::from_json(line).unwrap().to_string(), line);
+        }
+
+        let spaced = " [ 1, [-2 ,\n3 ], [ ] ] ";
+        assert_eq!(Item::from_str(spaced).to_string(), "[1,[-2,3],[]]");
+        assert_eq!(
+            Item::from_json(spaced).unwrap().to_string(),
+            "[1,[-2,3],[]]"
+        );
+        assert!(Item::from_str("[-1]") < Item::from_str("[0]"));
+        assert!(Item::parse("[-]").is_err());
+        assert!(Item::parse("[1 2]").is_err());
+
+        let value: Value = serde_json::json!([[1], 2, []]);
+        let item = Item::try_from(&value).unwrap();
+        assert_eq!(item.to_string(), "[[1],2,[]]");
+        assert_eq!(Value::from(&item), value);
+
+        assert!(Item::try_from(&serde_json::json!([1.5])).is_err());
+        assert!(Item::try_from(&serde_json::json!(["1"])).is_err());
+        assert!(Item::try_from(&serde_json::json!([4_000_000_000_i64])).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let pairs = split_to_vec_of_pairs(INPUT);
+
+        assert_eq!(explain_pairs(&pairs), REFERENCE_OUTPUT);
+
+        for (left, right) in &pairs {
+            let trace = Trace::new(left, right);
+            assert_eq!(trace.result, left.cmp(right));
+            assert_eq!(trace.rule().map(Rule::ordering), Some(trace.result));
+        }
+
+        let trace = Trace::new(&Item::from_str("[[2]]"), &Item::from_str("[2]"));
+        assert_eq!(trace.result, Ordering::Equal);
+        assert_eq!(trace.rule(), None);
+        assert_eq!(
+            trace.steps[1..3],
+            [
+                Step {
+                    depth: 1,
+                    kind: StepKind::Compare(ln(2), n(2))
+                },
+                Step {
+                    depth: 2,
+                    kind: StepKind::Promote(Side::Right, ln(2))
+                }
+            ]
+        );
+        assert_eq!(
+            trace.to_string(),
+            "- Compare [[2]] vs [2]
+  - Compare [2] vs 2
+    - Mixed types; convert right to [2] and retry comparison
+    - Compare [2] vs [2]
+      - Compare 2 vs 2"
+        );
+    }
+
     #[test]
     fn test_part2() {
         let product =
@@ -384,7 +717,7 @@ mod tests {
 [1,[2,[3,[4,[5,6,7]]]],8,9]
 [1,[2,[3,[4,[5,6,0]]]],8,9]";
 
-    const _REFERENCE_OUTPUT: &str = "== Pair 1 ==
+    const REFERENCE_OUTPUT: &str = "== Pair 1 ==
 - Compare [1,1,3,1,1] vs [1,1,5,1,1]
   - Compare 1 vs 1
   - Compare 1 vs 1
@@ -1,4 +1,4 @@
-use std::fs;
+use std::{env, fs};
 
 fn main() {
     let filename = "input.txt";
@@ -7,6 +7,62 @@ fn main() {
         Err(error) => panic!("Error reading file '{}': {}", filename, error),
     };
 
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("simulate") {
+        // drop grains one by one instead of flood-filling the cave
+        let mut physics = Physics::PART2;
+        if let Some(name) = args.get(2) {
+            physics.grain = *GRANULAR
+                .get(name.as_str())
+                .unwrap_or_else(|| panic!("Unknown material '{}'", name));
+        }
+        if let Some(boundary) = args.get(3) {
+            physics.boundary = match boundary.as_str() {
+                "abyss" => Boundary::Abyss,
+                "bedrock" => Boundary::Bedrock,
+                other => panic!("Unknown boundary '{}', use abyss or bedrock", other),
+            };
+        }
+
+        let mut field = Field::from_str(&input);
+        let count = field.count_sand_drops_along_path(physics);
+        println!("Simulated grains with {:?}: {}", physics, count);
+        return;
+    }
+
+    match args.get(1).map(String::as_str) {
+        Some("frames") => {
+            // watch part 2 fill up, one frame every few grains
+            let every: usize = args.get(2).map_or(1000, |every| every.parse().unwrap());
+            let mut field = Field::from_str(&input);
+            let count = field.count_sand_drops_until_filled_with(|count, field| {
+                if count % every == 0 {
+                    println!(
+                        "== {} grains ==\n{}\n",
+                        count,
+                        field.render(field.fit_viewport())
+                    );
+                }
+            });
+            println!(
+                "== {} grains ==\n{}",
+                count,
+                field.render(field.fit_viewport())
+            );
+            return;
+        }
+        Some("ppm") => {
+            let filename = args.get(2).map_or("cave.ppm", String::as_str);
+            let scale: usize = args.get(3).map_or(2, |scale| scale.parse().unwrap());
+            let mut field = Field::from_str(&input);
+            field.count_sand_drops_along_path(Physics::PART2);
+            fs::write(filename, field.to_ppm(field.fit_viewport(), scale)).unwrap();
+            println!("Wrote {}", filename);
+            return;
+        }
+        _ => {}
+    }
+
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
 }
@@ -14,33 +70,153 @@ fn main() {
 fn part1(input: &str) -> usize {
     let mut field = Field::from_str(input);
 
-    field.count_sand_drops_until_abyss()
+    field.count_sand_drops_along_path(Physics::PART1)
 }
 
 fn part2(input: &str) -> usize {
-    let mut field = Field::from_str(input);
+    let field = Field::from_str(input);
 
-    field.count_sand_drops_until_filled()
+    field.count_filled_by_flood()
 }
 
-#[derive(Clone)]
+type Pos = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Material {
     Air,
     Rock,
     Sand,
     Spawn,
+    Gravel,
+    Water,
+}
+
+// Granular materials by the name used on the command line.
+static GRANULAR: phf::Map<&'static str, Material> = phf::phf_map! {
+    "sand" => Material::Sand,
+    "gravel" => Material::Gravel,
+    "water" => Material::Water,
+};
+
+// How a granular material falls. Each step a grain tries `moves` in order
+// and comes to rest when none of them lead into air. A sideways move (one
+// with dy = 0) never undoes the previous move, and at most `drift` of them
+// happen in a row, so every grain settles eventually.
+struct FallRule {
+    moves: &'static [Pos],
+    drift: u32,
+}
+
+const FALL_RULES: [(Material, FallRule); 3] = [
+    (
+        Material::Sand,
+        FallRule {
+            moves: &[(0, 1), (-1, 1), (1, 1)],
+            drift: 0,
+        },
+    ),
+    (
+        Material::Gravel,
+        FallRule {
+            moves: &[(0, 1)],
+            drift: 0,
+        },
+    ),
+    (
+        Material::Water,
+        FallRule {
+            moves: &[(0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0)],
+            drift: 8,
+        },
+    ),
+];
+
+impl Material {
+    fn symbol(self) -> char {
+        match self {
+            Material::Air => '.',
+            Material::Rock => '#',
+            Material::Sand => 'o',
+            Material::Spawn => '+',
+            Material::Gravel => ':',
+            Material::Water => '~',
+        }
+    }
+
+    fn color(self) -> (u8, u8, u8) {
+        match self {
+            Material::Air => (20, 16, 14),
+            Material::Rock => (110, 105, 100),
+            Material::Sand => (230, 200, 120),
+            Material::Spawn => (230, 40, 40),
+            Material::Gravel => (150, 120, 90),
+            Material::Water => (60, 110, 230),
+        }
+    }
+
+    fn fall_rule(self) -> &'static FallRule {
+        FALL_RULES
+            .iter()
+            .find(|(material, _)| *material == self)
+            .map(|(_, rule)| rule)
+            .unwrap_or_else(|| panic!("{:?} does not fall", self))
+    }
+}
+
+// What happens to grains that leave the scanned part of the cave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    // Grains falling past the lowest rock or beside the outermost one are lost.
+    Abyss,
+    // An endless floor two rows below the lowest rock catches every grain.
+    Bedrock,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Physics {
+    grain: Material,
+    boundary: Boundary,
+}
+
+impl Physics {
+    const PART1: Physics = Physics {
+        grain: Material::Sand,
+        boundary: Boundary::Abyss,
+    };
+    const PART2: Physics = Physics {
+        grain: Material::Sand,
+        boundary: Boundary::Bedrock,
+    };
+}
+
+// A falling grain, with the state its fall rule needs to pick the next move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Grain {
+    pos: Pos,
+    last_move: Option<Pos>,
+    drift: u32,
+}
+
+impl Grain {
+    fn new(pos: Pos) -> Grain {
+        Grain {
+            pos,
+            last_move: None,
+            drift: 0,
+        }
+    }
 }
 
-fn parse_lines(input: &str) -> Vec<Vec<(usize, usize)>> {
+fn parse_lines(input: &str) -> Vec<Vec<Pos>> {
     input
         .trim()
         .lines()
         .map(|line| {
             line.split(" -> ")
                 .map(|point| {
-                    let coords: Vec<usize> = point
+                    let coords: Vec<i32> = point
                         .split(',')
-                        .map(|coord| coord.parse::<usize>().unwrap())
+                        .map(|coord| coord.trim().parse::<i32>().unwrap())
                         .collect();
                     (coords[0], coords[1])
                 })
@@ -49,192 +225,279 @@ fn parse_lines(input: &str) -> Vec<Vec<(usize, usize)>> {
         .collect()
 }
 
+// All cells on the line from `start` to `end`, both included. Works for any
+// slope, although cave scans only use straight and 45° lines.
+fn bresenham(start: Pos, end: Pos) -> Vec<Pos> {
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let sx = (end.0 - x).signum();
+    let sy = (end.1 - y).signum();
+    let mut error = dx + dy;
+    let mut cells = vec![(x, y)];
+
+    while (x, y) != end {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+        cells.push((x, y));
+    }
+
+    cells
+}
+
+// The cave grows sideways on demand, so sand may pile up at any x. Columns
+// are stored from `x_origin` and rows from `y_origin` down to the bedrock
+// floor two rows below the lowest rock, which is never stored but always
+// reads as rock.
 #[derive(Clone)]
 struct Field {
-    field: Vec<Vec<Material>>,
-    xmin: usize,
-    xmax: usize,
-    ymax: usize,
+    columns: Vec<Vec<Material>>,
+    x_origin: i32,
+    y_origin: i32,
+    spawn: Pos,
+    xmin: i32,
+    xmax: i32,
+    ymax: i32,
+}
+
+// A rectangle of cave cells, bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Viewport {
+    xmin: i32,
+    xmax: i32,
+    ymin: i32,
+    ymax: i32,
+}
+
+impl Viewport {
+    fn width(&self) -> usize {
+        (self.xmax - self.xmin + 1) as usize
+    }
+
+    fn height(&self) -> usize {
+        (self.ymax - self.ymin + 1) as usize
+    }
 }
 
 enum DropResult {
-    Falling(usize, usize),
-    Placed(usize, usize),
+    Falling(Grain),
+    Placed(i32, i32),
     Abyss,
     Filled,
 }
 
-type DropFn = fn(&Field, (usize, usize)) -> DropResult;
-
 impl Field {
-    const SPAWN: (usize, usize) = (500, 0);
+    const SPAWN: Pos = (500, 0);
 
     fn from_str(input: &str) -> Field {
+        Field::with_spawn(input, Field::SPAWN)
+    }
+
+    // Builds the cave with sand entering at `spawn`. The abyss bounds cover
+    // the rock structures and the spawn point.
+    fn with_spawn(input: &str, spawn: Pos) -> Field {
         let lines = parse_lines(input);
-        let xmin = lines
-            .iter()
-            .map(|line| line.iter().map(|(x, _)| x).min().unwrap())
-            .min()
-            .unwrap();
-        let xmax = lines
-            .iter()
-            .map(|line| line.iter().map(|(x, _)| x).max().unwrap())
-            .max()
-            .unwrap();
-        let ymax = lines
-            .iter()
-            .map(|line| line.iter().map(|(_, y)| y).max().unwrap())
-            .max()
-            .unwrap();
+        let points = lines.iter().flatten().chain([&spawn]);
 
+        let xmin = points.clone().map(|(x, _)| *x).min().unwrap();
+        let xmax = points.clone().map(|(x, _)| *x).max().unwrap();
+        let ymin = points.clone().map(|(_, y)| *y).min().unwrap();
+        let ymax = points.map(|(_, y)| *y).max().unwrap();
+
+        let height = (ymax + 2 - ymin) as usize;
         let mut field = Field {
-            field: vec![vec![Material::Air; ymax + 4]; xmax + (ymax + 4)],
-            xmin: *xmin,
-            xmax: *xmax,
-            ymax: *ymax,
+            columns: vec![vec![Material::Air; height]; (xmax - xmin + 1) as usize],
+            x_origin: xmin,
+            y_origin: ymin,
+            spawn,
+            xmin,
+            xmax,
+            ymax,
         };
 
-        if !field.contains(Field::SPAWN) {
-            panic!(
-                "Spawn point at {:?} not in field\n Field has dimensions {}-{} x 0-{}",
-                Field::SPAWN,
-                field.xmin,
-                field.xmax,
-                field.ymax
-            );
-        }
-
-        // draw bedrock at bottom of field
-        field.rock_line((0, ymax + 2), (field.field.len() - 1, ymax + 2));
-
         field.set_rock_lines(lines);
 
         // draw spawn point into field
-        if !matches!(field.field[Field::SPAWN.0][Field::SPAWN.1], Material::Air) {
-            panic!("Spawn point at {:?} is not air!", Field::SPAWN);
+        if !matches!(field.get(spawn), Material::Air) {
+            panic!("Spawn point at {:?} is not air!", spawn);
         }
 
-        field.field[Field::SPAWN.0][Field::SPAWN.1] = Material::Spawn;
+        field.set(spawn, Material::Spawn);
 
         field
     }
 
-    fn contains(&self, (x, y): (usize, usize)) -> bool {
-        x >= self.xmin && x <= self.xmax && y <= self.ymax
+    fn floor(&self) -> i32 {
+        self.ymax + 2
     }
 
-    #[cfg(test)]
-    fn is_valid_coordinate(&self, (x, y): (usize, usize)) -> bool {
-        self.field.len() > x && self.field[x].len() > y
+    fn contains(&self, (x, y): Pos) -> bool {
+        x >= self.xmin && x <= self.xmax && y <= self.ymax
     }
 
-    #[cfg(test)]
-    fn pretty_print(&self) -> String {
-        let mut result = String::new();
-        result.reserve(self.field.len() * (self.field[0].len() + 2));
+    fn get(&self, (x, y): Pos) -> Material {
+        if y >= self.floor() {
+            return Material::Rock;
+        }
+        // nothing is stored above the spawn point or left of the cave
+        if x < self.x_origin || y < self.y_origin {
+            return Material::Air;
+        }
 
-        for y in 0..=self.ymax {
-            if y > 0 {
-                result.push('\n');
-            }
-            for x in self.xmin..=self.xmax {
-                let c = match self.field[x][y] {
-                    Material::Air => '.',
-                    Material::Rock => '#',
-                    Material::Sand => 'o',
-                    Material::Spawn => '+',
-                };
-                result.push(c);
-            }
+        match self.columns.get((x - self.x_origin) as usize) {
+            Some(column) => column[(y - self.y_origin) as usize],
+            None => Material::Air,
         }
+    }
 
-        result
+    fn set(&mut self, (x, y): Pos, material: Material) {
+        let height = (self.floor() - self.y_origin) as usize;
+        assert!(
+            y >= self.y_origin && y < self.floor(),
+            "Row {} is outside the cave",
+            y
+        );
+
+        // grow by at least the current width so repeated spills stay cheap
+        if x < self.x_origin {
+            let grow = (self.x_origin - x).max(self.columns.len() as i32);
+            let new = vec![vec![Material::Air; height]; grow as usize];
+            self.columns.splice(0..0, new);
+            self.x_origin -= grow;
+        }
+        let column = (x - self.x_origin) as usize;
+        if column >= self.columns.len() {
+            let width = (column + 1).max(2 * self.columns.len());
+            self.columns.resize(width, vec![Material::Air; height]);
+        }
+
+        self.columns[column][(y - self.y_origin) as usize] = material;
     }
 
-    fn set_rock_lines(&mut self, lines: Vec<Vec<(usize, usize)>>) {
-        // draw rock lines into field
-        for line in lines {
-            for pair in line.windows(2) {
-                let start = pair[0];
-                let end = pair[1];
+    // The scanned part of the cave: the rock structures and the spawn point.
+    fn scan_viewport(&self) -> Viewport {
+        Viewport {
+            xmin: self.xmin,
+            xmax: self.xmax,
+            ymin: self.y_origin,
+            ymax: self.ymax,
+        }
+    }
 
-                self.rock_line(start, end);
+    // Grows the scanned viewport to every grain that spilled past it, down
+    // to and including the bedrock floor.
+    fn fit_viewport(&self) -> Viewport {
+        let mut viewport = self.scan_viewport();
+        viewport.ymax = self.floor();
+
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.iter().any(|material| *material != Material::Air) {
+                let x = self.x_origin + i as i32;
+                viewport.xmin = viewport.xmin.min(x);
+                viewport.xmax = viewport.xmax.max(x);
             }
         }
-    }
 
-    fn rock_line(&mut self, start: (usize, usize), end: (usize, usize)) {
-        let (x, y) = start;
-        let (xend, yend) = end;
+        viewport
+    }
 
-        use std::cmp::Ordering::*;
+    fn render(&self, viewport: Viewport) -> String {
+        let mut result = String::new();
+        result.reserve(viewport.width() * (viewport.height() + 1));
 
-        match (x.cmp(&xend), y.cmp(&yend)) {
-            // single point
-            (Equal, Equal) => {
-                self.field[x][y] = Material::Rock;
+        for y in viewport.ymin..=viewport.ymax {
+            if y > viewport.ymin {
+                result.push('\n');
             }
-
-            // vertical line, starting at top (i.e. lower y coordinate)
-            (Equal, Less) => {
-                for y in y..=yend {
-                    self.field[x][y] = Material::Rock;
-                }
+            for x in viewport.xmin..=viewport.xmax {
+                result.push(self.get((x, y)).symbol());
             }
+        }
 
-            // vertical line, starting at bottom (i.e. higher y coordinate)
-            (Equal, Greater) => {
-                for y in yend..=y {
-                    self.field[x][y] = Material::Rock;
-                }
-            }
+        result
+    }
 
-            // horizontal line, starting at left (i.e. lower x coordinate)
-            (Less, Equal) => {
-                for x in x..=xend {
-                    self.field[x][y] = Material::Rock;
-                }
+    #[cfg(test)]
+    fn pretty_print(&self) -> String {
+        self.render(self.scan_viewport())
+    }
+
+    // Plain PPM image of the viewport with each cell drawn as a
+    // `scale` × `scale` square.
+    fn to_ppm(&self, viewport: Viewport, scale: usize) -> String {
+        let width = viewport.width() * scale;
+        let height = viewport.height() * scale;
+
+        let mut image = format!("P3\n{} {}\n255\n", width, height);
+        for y in viewport.ymin..=viewport.ymax {
+            let line: Vec<String> = (viewport.xmin..=viewport.xmax)
+                .flat_map(|x| std::iter::repeat_n(self.get((x, y)).color(), scale))
+                .map(|(r, g, b)| format!("{} {} {}", r, g, b))
+                .collect();
+            let line = line.join(" ");
+
+            for _ in 0..scale {
+                image.push_str(&line);
+                image.push('\n');
             }
+        }
 
-            // horizontal line, starting at right (i.e. higher x coordinate)
-            (Greater, Equal) => {
-                for x in xend..=x {
-                    self.field[x][y] = Material::Rock;
-                }
+        image
+    }
+
+    fn set_rock_lines(&mut self, lines: Vec<Vec<Pos>>) {
+        // draw rock lines into field
+        for line in lines {
+            if let [point] = line[..] {
+                self.rock_line(point, point);
+            }
+            for pair in line.windows(2) {
+                self.rock_line(pair[0], pair[1]);
             }
+        }
+    }
 
-            // any kind of diagonal line
-            (_, _) => panic!("Diagonal lines not supported yet"),
+    fn rock_line(&mut self, start: Pos, end: Pos) {
+        for pos in bresenham(start, end) {
+            self.set(pos, Material::Rock);
         }
     }
 
-    fn drop_sand(&mut self, drop_fn: DropFn) -> DropResult {
-        let mut pos = Field::SPAWN;
+    fn drop_sand(&mut self, physics: Physics) -> DropResult {
+        let mut grain = Grain::new(self.spawn);
 
-        if !matches!(self.field[pos.0][pos.1], Material::Spawn) {
+        if !matches!(self.get(grain.pos), Material::Spawn) {
             return DropResult::Filled;
         }
 
         loop {
-            match drop_fn(self, pos) {
+            match self.fall_step(physics, grain) {
                 DropResult::Abyss => return DropResult::Abyss,
                 DropResult::Filled => return DropResult::Filled,
-                DropResult::Falling(x, y) => {
-                    pos = (x, y);
+                DropResult::Falling(next) => {
+                    grain = next;
                 }
                 DropResult::Placed(x, y) => {
-                    self.field[x][y] = Material::Sand;
+                    self.set((x, y), physics.grain);
                     return DropResult::Placed(x, y);
                 }
             }
         }
     }
 
+    #[cfg(test)]
     fn count_sand_drops_until_abyss(&mut self) -> usize {
         let mut count = 0;
 
         loop {
-            match self.drop_sand(sand_step_part1) {
+            match self.drop_sand(Physics::PART1) {
                 DropResult::Abyss => return count,
                 DropResult::Placed(_, _) => count += 1,
                 _ => panic!("Unexpected result"),
@@ -242,89 +505,136 @@ impl Field {
         }
     }
 
+    #[cfg(test)]
     fn count_sand_drops_until_filled(&mut self) -> usize {
+        self.count_sand_drops_until_filled_with(|_, _| {})
+    }
+
+    // Drops grains one at a time until the spawn point is covered, calling
+    // `on_grain` with the count and the cave after each grain comes to rest.
+    fn count_sand_drops_until_filled_with(
+        &mut self,
+        mut on_grain: impl FnMut(usize, &Field),
+    ) -> usize {
         let mut count = 0;
         loop {
-            match self.drop_sand(sand_step_part2) {
+            match self.drop_sand(Physics::PART2) {
                 DropResult::Filled => return count,
                 DropResult::Abyss => panic!("Abyss is impossible in part2"),
-                DropResult::Placed(_, _) => count += 1,
+                DropResult::Placed(_, _) => {
+                    count += 1;
+                    on_grain(count, self);
+                }
                 _ => panic!("Unexpected result"),
             }
         }
     }
-}
 
-fn sand_step_part1(field: &Field, pos: (usize, usize)) -> DropResult {
-    let (current_x, current_y) = pos;
+    // Moves a grain one step according to the fall rule of its material.
+    fn fall_step(&self, physics: Physics, grain: Grain) -> DropResult {
+        let rule = physics.grain.fall_rule();
+        let (x, y) = grain.pos;
 
-    // straight down
-    let x = current_x;
-    let y = current_y + 1;
-
-    if !field.contains((x, y)) {
-        return DropResult::Abyss;
-    }
+        for &(dx, dy) in rule.moves {
+            let sideways = dy == 0;
+            if sideways && (grain.drift == rule.drift || grain.last_move == Some((-dx, 0))) {
+                continue;
+            }
 
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
-    }
+            let next = (x + dx, y + dy);
 
-    // left and down
-    let x = current_x - 1;
+            if physics.boundary == Boundary::Abyss && !self.contains(next) {
+                return DropResult::Abyss;
+            }
 
-    if !field.contains((x, y)) {
-        return DropResult::Abyss;
-    }
+            if matches!(self.get(next), Material::Air) {
+                return DropResult::Falling(Grain {
+                    pos: next,
+                    last_move: Some((dx, dy)),
+                    drift: if sideways { grain.drift + 1 } else { 0 },
+                });
+            }
+        }
 
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
+        DropResult::Placed(x, y)
     }
 
-    // right and down
-    let x = current_x + 1;
-
-    if !field.contains((x, y)) {
-        return DropResult::Abyss;
-    }
+    // Like `drop_sand`, but resumes from the end of `path`, the states the
+    // previous grain fell through. A grain always comes to rest at the top
+    // of the path, so everything below it is still air for the next one.
+    fn drop_sand_along_path(&mut self, path: &mut Vec<Grain>, physics: Physics) -> DropResult {
+        let mut grain = match path.last() {
+            Some(&grain) => grain,
+            None => {
+                if !matches!(self.get(self.spawn), Material::Spawn) {
+                    return DropResult::Filled;
+                }
+                path.push(Grain::new(self.spawn));
+                Grain::new(self.spawn)
+            }
+        };
 
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
+        loop {
+            match self.fall_step(physics, grain) {
+                DropResult::Abyss => return DropResult::Abyss,
+                DropResult::Filled => return DropResult::Filled,
+                DropResult::Falling(next) => {
+                    path.push(next);
+                    grain = next;
+                }
+                DropResult::Placed(x, y) => {
+                    self.set((x, y), physics.grain);
+                    path.pop();
+                    return DropResult::Placed(x, y);
+                }
+            }
+        }
     }
 
-    DropResult::Placed(current_x, current_y)
-}
-
-fn sand_step_part2(field: &Field, pos: (usize, usize)) -> DropResult {
-    let (current_x, current_y) = pos;
-
-    // straight down
-    let x = current_x;
-    let y = current_y + 1;
-
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
-    }
+    // Counts the grains that come to rest before one falls into the abyss
+    // or the spawn point is covered.
+    fn count_sand_drops_along_path(&mut self, physics: Physics) -> usize {
+        let mut path = Vec::new();
+        let mut count = 0;
 
-    // left and down
-    let x = current_x - 1;
+        while let DropResult::Placed(_, _) = self.drop_sand_along_path(&mut path, physics) {
+            count += 1;
+        }
 
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
+        count
     }
 
-    // right and down
-    let x = current_x + 1;
+    // Sand (and only sand) on the bedrock floor ends up in every cell
+    // reachable from the spawn point by falling down, down-left or
+    // down-right, so the grains can be counted row by row without
+    // simulating them.
+    fn count_filled_by_flood(&self) -> usize {
+        let (spawn_x, spawn_y) = self.spawn;
+        // row[i] is the cell at x = left + i; sand spreads at most one column per row
+        let depth = self.floor() - spawn_y;
+        let left = spawn_x - depth;
+        let width = (2 * depth + 1) as usize;
+
+        let mut row = vec![false; width];
+        row[(spawn_x - left) as usize] = true;
+        let mut count = 1;
+
+        for y in spawn_y + 1..self.floor() {
+            let mut next = vec![false; width];
+
+            for i in 1..width - 1 {
+                let reachable = row[i - 1] || row[i] || row[i + 1];
+                if reachable && !matches!(self.get((left + i as i32, y)), Material::Rock) {
+                    next[i] = true;
+                    count += 1;
+                }
+            }
 
-    if matches!(field.field[x][y], Material::Air) {
-        return DropResult::Falling(x, y);
-    }
+            row = next;
+        }
 
-    if matches!(field.field[x][y], Material::Spawn) {
-        return DropResult::Filled;
+        count
     }
-
-    DropResult::Placed(current_x, current_y)
 }
 
 #[cfg(test)]
@@ -423,59 +733,275 @@ mod tests {
         assert_eq!(field.pretty_print(), ref_snapshots[0].1);
 
         assert!(matches!(
-            field.drop_sand(sand_step_part1),
+            field.drop_sand(Physics::PART1),
             DropResult::Placed(500, 8)
         ));
 
         assert_eq!(1, ref_snapshots[1].0);
         assert_eq!(field.pretty_print(), ref_snapshots[1].1);
 
-        field.drop_sand(sand_step_part1);
+        field.drop_sand(Physics::PART1);
         assert_eq!(2, ref_snapshots[2].0);
         assert_eq!(field.pretty_print(), ref_snapshots[2].1);
 
         for _ in 3..=5 {
-            field.drop_sand(sand_step_part1);
+            field.drop_sand(Physics::PART1);
         }
 
         assert_eq!(5, ref_snapshots[3].0);
         assert_eq!(field.pretty_print(), ref_snapshots[3].1);
 
         for _ in 6..=22 {
-            field.drop_sand(sand_step_part1);
+            field.drop_sand(Physics::PART1);
         }
 
         assert_eq!(22, ref_snapshots[4].0);
         assert_eq!(field.pretty_print(), ref_snapshots[4].1);
 
         for _ in 23..=24 {
-            field.drop_sand(sand_step_part1);
+            field.drop_sand(Physics::PART1);
         }
         assert_eq!(24, ref_snapshots[5].0);
         assert_eq!(field.pretty_print(), ref_snapshots[5].1);
 
-        assert!(matches!(
-            field.drop_sand(sand_step_part1),
-            DropResult::Abyss
-        ));
+        assert!(matches!(field.drop_sand(Physics::PART1), DropResult::Abyss));
 
         let mut field = Field::from_str(INPUT);
 
         assert_eq!(24, field.count_sand_drops_until_abyss());
     }
 
+    #[test]
+    fn test_path_and_flood() {
+        let reference = Field::from_str(INPUT);
+
+        let mut stepwise = reference.clone();
+        let mut along_path = reference.clone();
+        let mut path = Vec::new();
+        loop {
+            let expected = stepwise.drop_sand(Physics::PART1);
+            let actual = along_path.drop_sand_along_path(&mut path, Physics::PART1);
+            match (expected, actual) {
+                (DropResult::Placed(x, y), DropResult::Placed(x2, y2)) => {
+                    assert_eq!((x, y), (x2, y2))
+                }
+                (DropResult::Abyss, DropResult::Abyss) => break,
+                _ => panic!("Simulations disagree"),
+            }
+        }
+        assert_eq!(stepwise.pretty_print(), along_path.pretty_print());
+
+        let mut field = reference.clone();
+        assert_eq!(field.count_sand_drops_along_path(Physics::PART1), 24);
+        let mut field = reference.clone();
+        assert_eq!(field.count_sand_drops_along_path(Physics::PART2), 93);
+        assert_eq!(reference.count_filled_by_flood(), 93);
+
+        let input = fs::read_to_string("input.txt").unwrap();
+        let reference = Field::from_str(&input);
+        let (mut slow, mut fast) = (reference.clone(), reference.clone());
+        assert_eq!(
+            slow.count_sand_drops_until_abyss(),
+            fast.count_sand_drops_along_path(Physics::PART1)
+        );
+        let (mut slow, mut fast) = (reference.clone(), reference.clone());
+        let filled = slow.count_sand_drops_until_filled();
+        assert_eq!(filled, fast.count_sand_drops_along_path(Physics::PART2));
+        assert_eq!(filled, reference.count_filled_by_flood());
+    }
+
     #[test]
     fn test_part2() {
         let mut field = Field::from_str(INPUT);
 
-        assert!(field.is_valid_coordinate((500, field.ymax)));
-
-        assert!(matches!(field.field[500][field.ymax + 1], Material::Air));
-        assert!(matches!(field.field[500][field.ymax + 2], Material::Rock));
+        assert!(matches!(field.get((500, field.ymax + 1)), Material::Air));
+        assert!(matches!(field.get((500, field.ymax + 2)), Material::Rock));
 
         assert_eq!(93, field.count_sand_drops_until_filled());
     }
 
+    #[test]
+    fn test_geometry() {
+        assert_eq!(
+            bresenham((0, 0), (3, 3)),
+            vec![(0, 0), (1, 1), (2, 2), (3, 3)]
+        );
+        assert_eq!(bresenham((2, -1), (0, 1)), vec![(2, -1), (1, 0), (0, 1)]);
+        assert_eq!(
+            bresenham((0, 0), (4, 2)),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+        assert_eq!(bresenham((5, 5), (5, 5)), vec![(5, 5)]);
+
+        // a diagonal chute far left of a spawn point at negative coordinates
+        let mut field = Field::with_spawn("-10,3 -> -13,6\n-5,8", (-3, -2));
+        assert_eq!(
+            (field.xmin, field.xmax, field.y_origin, field.ymax),
+            (-13, -3, -2, 8)
+        );
+        assert_eq!(
+            field.pretty_print(),
+            "..........+
+...........
+...........
+...........
+...........
+...#.......
+..#........
+.#.........
+#..........
+...........
+........#.."
+        );
+
+        let mut flooded = field.clone();
+        let count = flooded.count_sand_drops_along_path(Physics::PART2);
+        assert_eq!(count, field.count_filled_by_flood());
+        // sand spilled well past the scanned structures
+        assert!(matches!(flooded.get((-3 - 11, 9)), Material::Sand));
+        assert!(matches!(flooded.get((-3 + 11, 9)), Material::Sand));
+
+        assert_eq!(field.count_sand_drops_along_path(Physics::PART1), 0);
+
+        // the cave can be read anywhere, even above the spawn point
+        assert!(matches!(field.get((-3, -3)), Material::Air));
+        assert!(matches!(field.get((-100, -100)), Material::Air));
+        assert!(matches!(field.get((100, field.floor())), Material::Rock));
+
+        let field = Field::with_spawn(INPUT, (490, 2));
+        assert_eq!(field.xmin, 490);
+        assert_eq!(
+            field.count_filled_by_flood(),
+            field.clone().count_sand_drops_until_filled()
+        );
+    }
+
+    #[test]
+    fn test_materials() {
+        let gravel = |boundary| Physics {
+            grain: Material::Gravel,
+            boundary,
+        };
+        let water = |boundary| Physics {
+            grain: Material::Water,
+            boundary,
+        };
+
+        // gravel only stacks up straight below the spawn point
+        let mut field = Field::from_str(INPUT);
+        assert_eq!(
+            field.count_sand_drops_along_path(gravel(Boundary::Abyss)),
+            9
+        );
+        assert!(matches!(field.get((500, 0)), Material::Gravel));
+        assert!(matches!(field.get((499, 8)), Material::Air));
+        assert_eq!(
+            Field::from_str(INPUT).count_sand_drops_along_path(gravel(Boundary::Bedrock)),
+            9
+        );
+
+        let mut field = Field::from_str(INPUT);
+        // water drifts along the bottom rock and drains into the abyss
+        assert_eq!(field.count_sand_drops_along_path(water(Boundary::Abyss)), 0);
+
+        let mut stepwise = Field::from_str(INPUT);
+        let mut along_path = Field::from_str(INPUT);
+        let mut path = Vec::new();
+        for _ in 0..100 {
+            let expected = stepwise.drop_sand(water(Boundary::Bedrock));
+            let actual = along_path.drop_sand_along_path(&mut path, water(Boundary::Bedrock));
+            match (expected, actual) {
+                (DropResult::Placed(x, y), DropResult::Placed(x2, y2)) => {
+                    assert_eq!((x, y), (x2, y2))
+                }
+                (DropResult::Filled, DropResult::Filled) => break,
+                _ => panic!("Simulations disagree"),
+            }
+        }
+        assert_eq!(stepwise.pretty_print(), along_path.pretty_print());
+        // the basin fills up to its rim
+        assert_eq!(
+            along_path.pretty_print(),
+            "......+...
+..........
+..........
+..........
+....#~~~##
+....#~~~#.
+..###~~~#.
+~~~~~~~~#.
+~~~~~~~~#.
+#########."
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut field = Field::from_str(INPUT);
+        let mut frames = Vec::new();
+        let count = field.count_sand_drops_until_filled_with(|count, field| {
+            if count % 31 == 0 {
+                frames.push(field.render(field.fit_viewport()));
+            }
+        });
+
+        assert_eq!(count, 93);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0],
+            "........+...
+............
+........o...
+.......ooo..
+......#ooo##
+.....o#ooo#.
+....###ooo#.
+...o..oooo#.
+..oooooooo#.
+.o#########.
+ooo.........
+############"
+        );
+
+        let viewport = field.fit_viewport();
+        assert_eq!(
+            viewport,
+            Viewport {
+                xmin: 490,
+                xmax: 510,
+                ymin: 0,
+                ymax: 11
+            }
+        );
+        assert_eq!(
+            field.render(viewport),
+            "..........o..........
+.........ooo.........
+........ooooo........
+.......ooooooo.......
+......oo#ooo##o......
+.....ooo#ooo#ooo.....
+....oo###ooo#oooo....
+...oooo.oooo#ooooo...
+..oooooooooo#oooooo..
+.ooo#########ooooooo.
+ooooo.......ooooooooo
+#####################"
+        );
+
+        let ppm = field.to_ppm(viewport, 2);
+        let mut lines = ppm.lines();
+        assert_eq!(lines.next(), Some("P3"));
+        assert_eq!(lines.next(), Some("42 24"));
+        assert_eq!(lines.next(), Some("255"));
+        assert_eq!(lines.count(), 24);
+        assert!(ppm
+            .lines()
+            .nth(3)
+            .unwrap()
+            .starts_with("20 16 14 20 16 14 20 16 14"));
+    }
+
     const INPUT: &str = "498,4 -> 498,6 -> 496,6
 503,4 -> 502,4 -> 502,9 -> 494,9";
 }
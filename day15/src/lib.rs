@@ -1,7 +1,19 @@
 pub mod day15 {
-    use std::cmp::Ordering;
+    //! Integer intervals and sets of them.
+    //!
+    //! [`Range`] is closed, so it includes both `start` and `end`, and
+    //! [`HalfOpen`] excludes its `end`. Either is empty when it holds no value
+    //! (`start > end` for a `Range`); every operation accepts empty intervals,
+    //! and the ones that build a new interval return [`Range::EMPTY`] rather
+    //! than an arbitrary inverted pair.
 
-    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    use std::{cmp::Ordering, ops::RangeInclusive};
+
+    /// The closed interval `start..=end`.
+    ///
+    /// Ranges sort by `start`, and the longer range first among equal starts,
+    /// so a sorted list can be merged in a single pass.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
     pub struct Range {
         pub start: i32,
         pub end: i32,
@@ -27,24 +39,46 @@ pub mod day15 {
     }
 
     impl Range {
+        /// The canonical empty range.
+        pub const EMPTY: Range = Range { start: 0, end: -1 };
+
         pub fn new(start: i32, end: i32) -> Self {
             Self { start, end }
         }
 
+        /// The number of values in the range, 0 if it is empty.
         pub fn len(&self) -> usize {
-            (self.end - self.start + 1) as usize
+            if self.is_empty() {
+                0
+            } else {
+                (self.end as i64 - self.start as i64 + 1) as usize
+            }
         }
 
         pub fn is_empty(&self) -> bool {
-            false
+            self.start > self.end
         }
 
         pub fn contains(&self, value: i32) -> bool {
             self.start <= value && value <= self.end
         }
 
+        /// Whether `self` holds every value of `other`.
+        pub fn covers(&self, other: &Self) -> bool {
+            other.is_empty() || (self.start <= other.start && other.end <= self.end)
+        }
+
+        /// The smallest range holding both, if they overlap or touch.
+        /// Joining with an empty range gives back the other one.
         pub fn join(&self, other: &Self) -> Option<Range> {
-            if self.start > other.end + 1 || other.start > self.end + 1 {
+            if self.is_empty() {
+                return Some(*other);
+            }
+            if other.is_empty() {
+                return Some(*self);
+            }
+            if self.start as i64 > other.end as i64 + 1 || other.start as i64 > self.end as i64 + 1
+            {
                 return None;
             }
 
@@ -54,34 +88,372 @@ pub mod day15 {
             Some(Range { start, end })
         }
 
+        /// The values in both ranges.
+        pub fn intersection(&self, other: &Self) -> Range {
+            let range = Range {
+                start: self.start.max(other.start),
+                end: self.end.min(other.end),
+            };
+
+            if range.is_empty() {
+                Range::EMPTY
+            } else {
+                range
+            }
+        }
+
+        /// Restricts the range to `limits`, leaving it empty if they don't
+        /// overlap.
         pub fn clip(&mut self, limits: &Self) {
-            self.start = self.start.max(limits.start);
-            self.end = self.end.min(limits.end);
+            *self = self.intersection(limits);
+        }
+
+        /// The values of `self` that are not in `other`: nothing, one range,
+        /// or two when `other` lies strictly inside `self`.
+        pub fn subtract(&self, other: &Self) -> Vec<Range> {
+            let overlap = self.intersection(other);
+            if overlap.is_empty() {
+                return if self.is_empty() { vec![] } else { vec![*self] };
+            }
+
+            let mut pieces = Vec::with_capacity(2);
+            if self.start < overlap.start {
+                pieces.push(Range::new(self.start, overlap.start - 1));
+            }
+            if overlap.end < self.end {
+                pieces.push(Range::new(overlap.end + 1, self.end));
+            }
+
+            pieces
+        }
+
+        /// The values from `start` to `end`, none if the range is empty.
+        pub fn iter(&self) -> RangeInclusive<i32> {
+            self.start..=self.end
+        }
+
+        /// The same values as a half-open interval, unless the range ends at
+        /// `i32::MAX` and the exclusive end cannot be represented.
+        pub fn to_half_open(&self) -> Option<HalfOpen> {
+            if self.is_empty() {
+                return Some(HalfOpen::new(0, 0));
+            }
+
+            self.end
+                .checked_add(1)
+                .map(|end| HalfOpen::new(self.start, end))
         }
 
+        /// Sorts the ranges and merges the ones that overlap or touch, dropping
+        /// empty ones. The result is sorted and pairwise disjoint.
         pub fn join_vec(ranges: Vec<Range>) -> Vec<Range> {
             let mut ranges = ranges;
+            ranges.retain(|range| !range.is_empty());
             ranges.sort();
 
-            ranges
+            let mut joined: Vec<Range> = Vec::with_capacity(ranges.len());
+            for range in ranges {
+                match joined
+                    .last_mut()
+                    .and_then(|last| last.join(&range).map(|j| (last, j)))
+                {
+                    Some((last, join)) => *last = join,
+                    None => joined.push(range),
+                }
+            }
+
+            joined
+        }
+    }
+
+    impl IntoIterator for Range {
+        type Item = i32;
+        type IntoIter = RangeInclusive<i32>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl From<RangeInclusive<i32>> for Range {
+        fn from(range: RangeInclusive<i32>) -> Self {
+            Range::new(*range.start(), *range.end())
+        }
+    }
+
+    impl From<HalfOpen> for Range {
+        fn from(range: HalfOpen) -> Self {
+            if range.is_empty() {
+                Range::EMPTY
+            } else {
+                Range::new(range.start, range.end - 1)
+            }
+        }
+    }
+
+    /// The half-open interval `start..end`, empty when `start >= end`.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+    pub struct HalfOpen {
+        pub start: i32,
+        pub end: i32,
+    }
+
+    impl HalfOpen {
+        pub fn new(start: i32, end: i32) -> Self {
+            Self { start, end }
+        }
+
+        pub fn len(&self) -> usize {
+            if self.is_empty() {
+                0
+            } else {
+                (self.end as i64 - self.start as i64) as usize
+            }
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.start >= self.end
+        }
+
+        pub fn contains(&self, value: i32) -> bool {
+            self.start <= value && value < self.end
+        }
+
+        pub fn iter(&self) -> std::ops::Range<i32> {
+            self.start..self.end
+        }
+
+        pub fn to_closed(&self) -> Range {
+            Range::from(*self)
+        }
+    }
+
+    impl IntoIterator for HalfOpen {
+        type Item = i32;
+        type IntoIter = std::ops::Range<i32>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl From<std::ops::Range<i32>> for HalfOpen {
+        fn from(range: std::ops::Range<i32>) -> Self {
+            HalfOpen::new(range.start, range.end)
+        }
+    }
+
+    /// A set of integers, stored as sorted, disjoint and non-touching closed
+    /// ranges so that lookups are a binary search.
+    #[derive(Debug, Clone, Default, Eq, PartialEq)]
+    pub struct RangeSet {
+        ranges: Vec<Range>,
+    }
+
+    impl RangeSet {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn from_ranges(ranges: Vec<Range>) -> Self {
+            Self {
+                ranges: Range::join_vec(ranges),
+            }
+        }
+
+        pub fn ranges(&self) -> &[Range] {
+            &self.ranges
+        }
+
+        /// The number of integers in the set.
+        pub fn len(&self) -> usize {
+            self.ranges.iter().map(Range::len).sum()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.ranges.is_empty()
+        }
+
+        /// The stored range holding `value`, if any.
+        pub fn range_of(&self, value: i32) -> Option<Range> {
+            let i = self.ranges.partition_point(|range| range.end < value);
+
+            self.ranges
+                .get(i)
+                .filter(|range| range.contains(value))
+                .copied()
+        }
+
+        pub fn contains(&self, value: i32) -> bool {
+            self.range_of(value).is_some()
+        }
+
+        /// Adds the values of `range`, merging it with the ranges it overlaps
+        /// or touches.
+        pub fn insert(&mut self, range: Range) {
+            if range.is_empty() {
+                return;
+            }
+
+            // the stored ranges from `first` up to `last` (exclusive) touch `range`
+            let first = self
+                .ranges
+                .partition_point(|r| (r.end as i64) < range.start as i64 - 1);
+            let last = self
+                .ranges
+                .partition_point(|r| r.start as i64 <= range.end as i64 + 1);
+
+            let merged = self.ranges[first..last]
                 .iter()
-                .fold(Vec::<Range>::new(), |mut acc, range| match acc.pop() {
-                    Some(last) => match last.join(range) {
-                        Some(joined_range) => {
-                            acc.push(joined_range);
-                            acc.to_vec()
-                        }
-                        None => {
-                            acc.push(last);
-                            acc.push(*range);
-                            acc.to_vec()
-                        }
-                    },
-                    None => {
-                        acc.push(*range);
-                        acc.to_vec()
-                    }
-                })
+                .fold(range, |acc, r| acc.join(r).unwrap());
+            self.ranges.splice(first..last, [merged]);
+        }
+
+        /// Removes the values of `range` from the set.
+        pub fn remove(&mut self, range: Range) {
+            if range.is_empty() {
+                return;
+            }
+
+            let first = self.ranges.partition_point(|r| r.end < range.start);
+            let last = self.ranges.partition_point(|r| r.start <= range.end);
+
+            let remaining: Vec<Range> = self.ranges[first..last]
+                .iter()
+                .flat_map(|r| r.subtract(&range))
+                .collect();
+            self.ranges.splice(first..last, remaining);
+        }
+
+        /// The maximal ranges of `within` that are not in the set, none if
+        /// `within` is empty.
+        pub fn gaps(&self, within: Range) -> Vec<Range> {
+            if within.is_empty() {
+                return vec![];
+            }
+            let mut gaps = vec![within];
+
+            // later ranges lie further right, so only the last gap can shrink
+            for range in &self.ranges {
+                if let Some(last) = gaps.pop() {
+                    gaps.extend(last.subtract(range));
+                }
+            }
+
+            gaps
+        }
+
+        /// Every integer in the set, in increasing order.
+        pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+            self.ranges.iter().flat_map(Range::iter)
+        }
+    }
+
+    impl FromIterator<Range> for RangeSet {
+        fn from_iter<T: IntoIterator<Item = Range>>(iter: T) -> Self {
+            Self::from_ranges(iter.into_iter().collect())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_range_edge_cases() {
+            let inverted = Range::new(5, 2);
+            assert!(inverted.is_empty());
+            assert_eq!(inverted.len(), 0);
+            assert_eq!(inverted.iter().count(), 0);
+            assert!(!Range::new(3, 3).is_empty());
+            assert_eq!(Range::new(i32::MIN, i32::MAX).len(), 1 << 32);
+
+            let mut range = Range::new(1, 10);
+            range.clip(&Range::new(20, 30));
+            assert_eq!(range, Range::EMPTY);
+            let mut range = Range::new(1, 10);
+            range.clip(&Range::new(5, 30));
+            assert_eq!(range, Range::new(5, 10));
+
+            assert_eq!(Range::new(1, 3).join(&Range::EMPTY), Some(Range::new(1, 3)));
+            assert_eq!(
+                Range::new(1, 3).join(&Range::new(4, 4)),
+                Some(Range::new(1, 4))
+            );
+            assert_eq!(
+                Range::new(i32::MAX - 1, i32::MAX).join(&Range::new(i32::MIN, 0)),
+                None
+            );
+            assert!(Range::new(1, 10).covers(&Range::new(3, 4)));
+            assert!(!Range::new(1, 10).covers(&Range::new(3, 11)));
+
+            assert_eq!(
+                Range::new(1, 10).subtract(&Range::new(4, 6)),
+                vec![Range::new(1, 3), Range::new(7, 10)]
+            );
+            assert_eq!(
+                Range::new(1, 10).subtract(&Range::new(-4, 6)),
+                vec![Range::new(7, 10)]
+            );
+            assert_eq!(Range::new(1, 10).subtract(&Range::new(0, 12)), vec![]);
+            assert_eq!(
+                Range::new(1, 10).subtract(&Range::new(12, 20)),
+                vec![Range::new(1, 10)]
+            );
+
+            assert_eq!(
+                Range::new(2, 4).into_iter().collect::<Vec<_>>(),
+                vec![2, 3, 4]
+            );
+            assert_eq!(Range::from(2..=4), Range::new(2, 4));
+
+            let half_open = HalfOpen::from(2..5);
+            assert_eq!(half_open.len(), 3);
+            assert!(!half_open.contains(5));
+            assert_eq!(half_open.to_closed(), Range::new(2, 4));
+            assert_eq!(Range::new(2, 4).to_half_open(), Some(half_open));
+            assert_eq!(Range::new(0, i32::MAX).to_half_open(), None);
+            assert!(HalfOpen::new(3, 3).is_empty());
+            assert_eq!(HalfOpen::new(3, 3).to_closed(), Range::EMPTY);
+
+            assert_eq!(
+                Range::join_vec(vec![Range::new(8, 9), Range::new(4, 1), Range::new(1, 7)]),
+                vec![Range::new(1, 9)]
+            );
+
+            let mut set: RangeSet = [Range::new(1, 3), Range::new(10, 12)].into_iter().collect();
+            assert_eq!(set.len(), 6);
+            assert!(set.contains(2) && !set.contains(5) && set.contains(12));
+            assert_eq!(set.range_of(11), Some(Range::new(10, 12)));
+
+            set.insert(Range::new(4, 5));
+            assert_eq!(set.ranges(), [Range::new(1, 5), Range::new(10, 12)]);
+            set.insert(Range::new(6, 9));
+            assert_eq!(set.ranges(), [Range::new(1, 12)]);
+            set.insert(Range::new(20, 25));
+            set.insert(Range::new(15, 15));
+            assert_eq!(
+                set.ranges(),
+                [Range::new(1, 12), Range::new(15, 15), Range::new(20, 25)]
+            );
+
+            set.remove(Range::new(3, 21));
+            assert_eq!(set.ranges(), [Range::new(1, 2), Range::new(22, 25)]);
+            assert_eq!(set.iter().collect::<Vec<_>>(), vec![1, 2, 22, 23, 24, 25]);
+
+            assert_eq!(
+                set.gaps(Range::new(0, 30)),
+                vec![Range::new(0, 0), Range::new(3, 21), Range::new(26, 30)]
+            );
+            assert_eq!(set.gaps(Range::new(22, 24)), vec![]);
+            assert_eq!(set.gaps(Range::EMPTY), vec![]);
+            assert!(RangeSet::new().is_empty());
+            assert_eq!(RangeSet::new().gaps(Range::EMPTY), vec![]);
+            assert_eq!(RangeSet::new().gaps(Range::new(4, 1)), vec![]);
+            assert_eq!(
+                RangeSet::new().gaps(Range::new(1, 4)),
+                vec![Range::new(1, 4)]
+            );
         }
     }
 }
@@ -1,13 +1,38 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs,
+    collections::{BTreeSet, HashMap, HashSet},
+    env, fmt, fs,
 };
 
-use day15::day15::Range;
+use day15::day15::{Range, RangeSet};
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
 
+    if env::args().nth(1).as_deref() == Some("coverage") {
+        let limit: i32 = env::args()
+            .nth(2)
+            .map_or(4_000_000, |limit| limit.parse().unwrap());
+        let field = Field::from(&input);
+        let bounds = Range::new(0, limit);
+
+        println!("Covered area: {}", field.covered_area(bounds, bounds));
+        for region in field.uncovered_regions(bounds, bounds) {
+            println!("{}", region);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("scan") {
+        // the slow row-by-row search, to cross-check the geometric solver
+        let lost_beacon = Field::from(&input).find_lost_beacon(4_000_000);
+        println!(
+            "Row scan found {:?}: {}",
+            lost_beacon,
+            lost_beacon.tuning_frequency()
+        );
+        return;
+    }
+
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
 }
@@ -34,9 +59,10 @@ fn part1(input: &str) -> usize {
 fn part2(input: &str) -> usize {
     let field = Field::from(input);
 
-    let lost_beacon = field.find_lost_beacon(4_000_000);
+    let lost_beacons = field.find_uncovered_points_next_to_coverage(4_000_000);
+    assert_eq!(lost_beacons.len(), 1);
 
-    lost_beacon.tuning_frequency()
+    lost_beacons[0].tuning_frequency()
 }
 
 type Position = (i32, i32);
@@ -61,7 +87,8 @@ fn beaconless_positions(sensor: &Sensor, range: i32, line: i32) -> Option<Range>
     let (x, y) = *sensor;
     let span = range - (line - y).abs();
 
-    if span <= 0 {
+    // a span of 0 still covers the single point straight above or below
+    if span < 0 {
         return None;
     }
 
@@ -132,48 +159,263 @@ impl Field {
             }
         });
 
-        // keep sensors, keep beacons
-
-        // join blocked positions
+        // join blocked positions; the first gap inside the limits holds the beacon
         let blocked_positions = Range::join_vec(blocked_positions);
 
-        match blocked_positions.len() {
-            0 => None,
-            1 => {
-                let range = blocked_positions[0];
-
-                if range.start == 1 {
-                    Some((range.end, line))
-                } else if range.end == limit.end - 1 {
-                    Some((range.start, line))
-                } else {
-                    None
+        uncovered_along(limit, &blocked_positions, 1)
+            .first()
+            .map(|&x| (x, line))
+    }
+
+    fn find_lost_beacon(&self, limit: i32) -> Beacon {
+        let beacons: Vec<Beacon> = (0..=limit)
+            .filter_map(|line| self.find_unknown_beacon_in_line(line, limit))
+            .collect();
+
+        assert_eq!(beacons.len(), 1);
+
+        beacons[0]
+    }
+}
+
+impl Field {
+    // Each sensor diamond as a square in rotated coordinates u = x + y and
+    // v = x - y; a point is covered when its (u, v) lies inside the square.
+    fn rotated_squares(&self) -> Vec<(Range, Range)> {
+        self.sensors
+            .iter()
+            .map(|(&(x, y), &range)| {
+                let (u, v) = (x + y, x - y);
+                (
+                    Range::new(u - range, u + range),
+                    Range::new(v - range, v + range),
+                )
+            })
+            .collect()
+    }
+
+    // Finds the uncovered points in the box `0..=limit` × `0..=limit` that
+    // have a covered neighbour. Such a point is one step outside some
+    // diamond, on the border of its square grown by one, so only those 4·n
+    // segments are searched: O(n² log n) for n sensors, however large the
+    // box is.
+    //
+    // Uncovered points surrounded by other uncovered points are not
+    // reported; `uncovered_regions` enumerates all of them row by row. The
+    // lost beacon of part 2 is the only uncovered point, so its neighbours
+    // are covered.
+    fn find_uncovered_points_next_to_coverage(&self, limit: i32) -> Vec<Position> {
+        let squares = self.rotated_squares();
+        let mut points = BTreeSet::new();
+
+        for (us, vs) in &squares {
+            let (outer_us, outer_vs) = (
+                Range::new(us.start - 1, us.end + 1),
+                Range::new(vs.start - 1, vs.end + 1),
+            );
+
+            for v in [outer_vs.start, outer_vs.end] {
+                // inside the box, v = x - y runs from -limit to limit
+                if v.abs() > limit {
+                    continue;
                 }
-            }
-            2 => {
-                let range1 = blocked_positions[0];
-                let range2 = blocked_positions[1];
+                let box_line = Range::new(v.abs(), 2 * limit - v.abs());
+                let covered: Vec<Range> = squares
+                    .iter()
+                    .filter(|(_, vs)| vs.contains(v))
+                    .map(|(us, _)| *us)
+                    .collect();
 
-                assert!(range1.start <= 0);
-                assert!(range2.end >= limit.end);
+                let segment = outer_us.intersection(&box_line);
+                for u in uncovered_along(segment, &Range::join_vec(covered), 2) {
+                    points.insert(((u + v) / 2, (u - v) / 2));
+                }
+            }
 
-                assert_eq!(range1.end + 2, range2.start);
+            for u in [outer_us.start, outer_us.end] {
+                // inside the box, u = x + y runs from 0 to 2 * limit
+                if u < 0 || u > 2 * limit {
+                    continue;
+                }
+                let box_line = Range::new((-u).max(u - 2 * limit), u.min(2 * limit - u));
+                let covered: Vec<Range> = squares
+                    .iter()
+                    .filter(|(us, _)| us.contains(u))
+                    .map(|(_, vs)| *vs)
+                    .collect();
 
-                Some((range1.end + 1, line))
+                let segment = outer_vs.intersection(&box_line);
+                for v in uncovered_along(segment, &Range::join_vec(covered), 2) {
+                    points.insert(((u + v) / 2, (u - v) / 2));
+                }
             }
-            _ => panic!("Too many potential unknown beacons, this must be wrong"),
         }
+
+        points.into_iter().collect()
     }
+}
 
-    fn find_lost_beacon(&self, limit: i32) -> Beacon {
-        let beacons: Vec<Beacon> = (0..=limit)
-            .filter_map(|line| self.find_unknown_beacon_in_line(line, limit))
+// A 4-connected set of uncovered points, stored as one x range per row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    rows: Vec<(i32, Range)>,
+}
+
+impl Region {
+    fn area(&self) -> u64 {
+        self.rows.iter().map(|(_, range)| range.len() as u64).sum()
+    }
+
+    // The bounding box as x and y ranges.
+    fn bounds(&self) -> (Range, Range) {
+        let xmin = self
+            .rows
+            .iter()
+            .map(|(_, range)| range.start)
+            .min()
+            .unwrap();
+        let xmax = self.rows.iter().map(|(_, range)| range.end).max().unwrap();
+        let ymin = self.rows.first().unwrap().0;
+        let ymax = self.rows.last().unwrap().0;
+
+        (Range::new(xmin, xmax), Range::new(ymin, ymax))
+    }
+
+    fn is_rectangle(&self) -> bool {
+        let (xs, ys) = self.bounds();
+        self.area() == xs.len() as u64 * ys.len() as u64
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (xs, ys) = self.bounds();
+        let shape = if self.is_rectangle() {
+            "rectangle"
+        } else {
+            "region"
+        };
+
+        write!(
+            f,
+            "{} x={}..={} y={}..={} with {} points",
+            shape,
+            xs.start,
+            xs.end,
+            ys.start,
+            ys.end,
+            self.area()
+        )
+    }
+}
+
+impl Field {
+    // The positions on `line` within `xs` that some sensor covers.
+    fn covered_in_line(&self, line: i32, xs: Range) -> RangeSet {
+        self.sensors
+            .iter()
+            .filter_map(|(sensor, &range)| beaconless_positions(sensor, range, line))
+            .map(|range| range.intersection(&xs))
+            .collect()
+    }
+
+    // The number of points in the box `xs` × `ys` that at least one sensor
+    // covers, counted one row at a time: O(rows × sensors), so only meant
+    // for boxes of moderate height.
+    fn covered_area(&self, xs: Range, ys: Range) -> u64 {
+        (ys.start..=ys.end)
+            .map(|line| self.covered_in_line(line, xs).len() as u64)
+            .sum()
+    }
+
+    // Splits the uncovered points in the box `xs` × `ys` into maximal
+    // connected regions, sorted by their first row and leftmost gap. Like
+    // `covered_area` it scans every row, in O(rows × sensors).
+    fn uncovered_regions(&self, xs: Range, ys: Range) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        // merged regions point at the region they were moved into
+        let mut merged_into: Vec<usize> = Vec::new();
+        let mut previous_row: Vec<(Range, usize)> = Vec::new();
+
+        fn find(merged_into: &[usize], mut id: usize) -> usize {
+            while merged_into[id] != id {
+                id = merged_into[id];
+            }
+            id
+        }
+
+        for line in ys.start..=ys.end {
+            let covered = self.covered_in_line(line, xs);
+            let mut row = Vec::new();
+
+            for x in covered.gaps(xs) {
+                let mut touching = previous_row
+                    .iter()
+                    .filter(|(range, _)| range.start <= x.end && x.start <= range.end)
+                    .map(|&(_, id)| find(&merged_into, id));
+
+                let id = match touching.next() {
+                    Some(id) => {
+                        for other in touching.collect::<Vec<_>>() {
+                            if other != id {
+                                let rows = std::mem::take(&mut regions[other].rows);
+                                regions[id].rows.extend(rows);
+                                merged_into[other] = id;
+                            }
+                        }
+                        id
+                    }
+                    None => {
+                        regions.push(Region { rows: Vec::new() });
+                        merged_into.push(regions.len() - 1);
+                        regions.len() - 1
+                    }
+                };
+
+                regions[id].rows.push((line, x));
+                row.push((x, id));
+            }
+
+            previous_row = row;
+        }
+
+        let mut regions: Vec<Region> = regions
+            .into_iter()
+            .filter(|region| !region.rows.is_empty())
             .collect();
+        for region in &mut regions {
+            region
+                .rows
+                .sort_by_key(|&(line, range)| (line, range.start));
+        }
 
-        assert_eq!(beacons.len(), 1);
+        regions
+    }
+}
 
-        beacons[0]
+// The values of `line` outside every one of the sorted, disjoint `covered`
+// ranges, visiting only every `step`-th value from `line.start`. Rotated
+// lines use a step of 2, because only u and v of equal parity map back to
+// integer x and y.
+fn uncovered_along(line: Range, covered: &[Range], step: i32) -> Vec<i32> {
+    let mut values = Vec::new();
+    let mut next = line.start;
+
+    for range in covered
+        .iter()
+        .chain([&Range::new(line.end + 1, line.end + 1)])
+    {
+        while next < range.start && next <= line.end {
+            values.push(next);
+            next += step;
+        }
+        while next <= range.end {
+            // skip the covered values, keeping the stride
+            next += step * ((range.end - next) / step + 1);
+        }
     }
+
+    values
 }
 
 fn parse_line(line: &str) -> (Sensor, Beacon) {
@@ -212,6 +454,20 @@ mod tests {
         assert_eq!(field.count_empty_positions_in_line(10), 26);
     }
 
+    #[test]
+    fn test_diamond_tip() {
+        // the sensor reaches 2 rows up and down, covering one point at the tips
+        assert_eq!(beaconless_positions(&(0, 0), 2, -2), Some(Range::new(0, 0)));
+        assert_eq!(beaconless_positions(&(0, 0), 2, 2), Some(Range::new(0, 0)));
+        assert_eq!(beaconless_positions(&(0, 0), 2, 3), None);
+
+        let field = Field::from("Sensor at x=0, y=0: closest beacon is at x=0, y=2");
+        assert_eq!(field.count_empty_positions_in_line(-2), 1);
+        // the beacon itself is the tip on the other side
+        assert_eq!(field.count_empty_positions_in_line(2), 0);
+        assert_eq!(field.count_empty_positions_in_line(1), 3);
+    }
+
     #[test]
     fn test_part2() {
         let input = INPUT;
@@ -226,6 +482,146 @@ mod tests {
         assert_eq!(lost_beacon.tuning_frequency(), 56000011);
     }
 
+    #[test]
+    fn test_coverage() {
+        let field = Field::from(INPUT);
+        let box_20 = Range::new(0, 20);
+
+        assert_eq!(field.covered_area(box_20, box_20), 21 * 21 - 1);
+        let regions = field.uncovered_regions(box_20, box_20);
+        assert_eq!(
+            regions,
+            vec![Region {
+                rows: vec![(11, Range::new(14, 14))]
+            }]
+        );
+        assert!(regions[0].is_rectangle());
+        assert_eq!(
+            regions[0].to_string(),
+            "rectangle x=14..=14 y=11..=11 with 1 points"
+        );
+
+        // compare a larger box against testing every point
+        let (xs, ys) = (Range::new(-8, 30), Range::new(-5, 28));
+        let uncovered: Vec<Position> = (ys.start..=ys.end)
+            .flat_map(|y| (xs.start..=xs.end).map(move |x| (x, y)))
+            .filter(|p| {
+                !field
+                    .sensors
+                    .iter()
+                    .any(|(sensor, &range)| manhattan_distance(sensor, p) <= range)
+            })
+            .collect();
+        let area = (xs.len() * ys.len()) as u64;
+        assert_eq!(field.covered_area(xs, ys), area - uncovered.len() as u64);
+
+        let regions = field.uncovered_regions(xs, ys);
+        let total: u64 = regions.iter().map(Region::area).sum();
+        assert_eq!(total, uncovered.len() as u64);
+        assert!(regions
+            .iter()
+            .any(|region| region.rows == vec![(11, Range::new(14, 14))]));
+        for region in &regions {
+            for &(line, range) in &region.rows {
+                assert!((range.start..=range.end).all(|x| uncovered.contains(&(x, line))));
+            }
+        }
+        // no two regions touch each other
+        for (i, a) in regions.iter().enumerate() {
+            for b in &regions[i + 1..] {
+                for &(line_a, ra) in &a.rows {
+                    for &(line_b, rb) in &b.rows {
+                        let adjacent_rows = (line_a - line_b).abs() <= 1;
+                        let same_row_touch =
+                            line_a == line_b && ra.start <= rb.end + 1 && rb.start <= ra.end + 1;
+                        let overlap = ra.start <= rb.end && rb.start <= ra.end;
+                        assert!(!(same_row_touch || (adjacent_rows && overlap)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_uncovered_hole() {
+        // pairs of sensors on every side and one in each corner leave a 2×2 hole
+        let input = "Sensor at x=0, y=4: closest beacon is at x=0, y=7
+Sensor at x=0, y=5: closest beacon is at x=0, y=8
+Sensor at x=9, y=4: closest beacon is at x=9, y=7
+Sensor at x=9, y=5: closest beacon is at x=9, y=8
+Sensor at x=4, y=0: closest beacon is at x=4, y=3
+Sensor at x=5, y=0: closest beacon is at x=5, y=3
+Sensor at x=4, y=9: closest beacon is at x=4, y=6
+Sensor at x=5, y=9: closest beacon is at x=5, y=6
+Sensor at x=0, y=0: closest beacon is at x=0, y=6
+Sensor at x=9, y=0: closest beacon is at x=9, y=6
+Sensor at x=0, y=9: closest beacon is at x=0, y=3
+Sensor at x=9, y=9: closest beacon is at x=9, y=3";
+        let field = Field::from(input);
+        let bounds = Range::new(0, 9);
+
+        assert_eq!(
+            field.find_uncovered_points_next_to_coverage(9),
+            vec![(4, 4), (4, 5), (5, 4), (5, 5)]
+        );
+        assert_eq!(
+            field.uncovered_regions(bounds, bounds),
+            vec![Region {
+                rows: vec![(4, Range::new(4, 5)), (5, Range::new(4, 5))]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rotated_solver() {
+        let field = Field::from(INPUT);
+
+        assert_eq!(
+            field.find_uncovered_points_next_to_coverage(20),
+            vec![(14, 11)]
+        );
+
+        // a larger box leaves an uncovered corner, of which only the points
+        // next to coverage are reported
+        let covered = |&(x, y): &Position| {
+            field
+                .sensors
+                .iter()
+                .any(|(sensor, &range)| manhattan_distance(sensor, &(x, y)) <= range)
+        };
+        let next_to_coverage = |&(x, y): &Position| {
+            [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .any(covered)
+        };
+        for limit in [25, 30] {
+            let expected: Vec<Position> = (0..=limit)
+                .flat_map(|x| (0..=limit).map(move |y| (x, y)))
+                .filter(|p| !covered(p) && next_to_coverage(p))
+                .collect();
+            assert_eq!(
+                field.find_uncovered_points_next_to_coverage(limit),
+                expected
+            );
+        }
+        assert!(field
+            .find_uncovered_points_next_to_coverage(30)
+            .contains(&(14, 11)));
+
+        assert_eq!(
+            uncovered_along(Range::new(0, 10), &[Range::new(2, 3), Range::new(5, 8)], 1),
+            vec![0, 1, 4, 9, 10]
+        );
+        assert_eq!(
+            uncovered_along(Range::new(1, 11), &[Range::new(2, 3), Range::new(6, 8)], 2),
+            vec![1, 5, 9, 11]
+        );
+        assert_eq!(
+            uncovered_along(Range::new(0, 4), &[Range::new(-5, 9)], 1),
+            vec![]
+        );
+    }
+
     const INPUT: &str = "Sensor at x=2, y=18: closest beacon is at x=-2, y=15
 Sensor at x=9, y=16: closest beacon is at x=10, y=16
 Sensor at x=13, y=2: closest beacon is at x=15, y=3
@@ -1,11 +1,54 @@
 use itertools::{Either, Itertools};
 use sscanf::sscanf;
-use std::collections::{HashMap, VecDeque};
-use std::fs;
+use std::collections::HashMap;
+use std::{env, fs};
 
 fn main() {
     let input = fs::read_to_string("input.txt").unwrap();
 
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("plan") {
+        // plan <agents> <minutes> [start valves] [opening cost], where agent i
+        // starts at the i-th of the comma-separated valves, wrapping around
+        let starts: Vec<&str> = args
+            .get(4)
+            .map_or(vec!["AA"], |starts| starts.split(',').collect());
+        let mut problem = ProblemStatement::from_graph(&ValveGraph::from_str(&input), &starts);
+        let count: usize = args.get(2).map_or(2, |count| count.parse().unwrap());
+        let time: usize = args.get(3).map_or(26, |time| time.parse().unwrap());
+        if let Some(cost) = args.get(5) {
+            problem.opening_cost = cost.parse().unwrap();
+        }
+
+        let agents: Vec<Agent> = (0..count)
+            .map(|i| Agent::new(problem.index_of(starts[i % starts.len()]).unwrap(), time))
+            .collect();
+        let plan = problem.plan(&agents);
+        println!("{}", plan.describe(&problem.names));
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("dot") {
+        // dot [compressed]: the tunnels, or only the valves worth opening
+        let graph = ValveGraph::from_str(&input);
+        if args.get(2).map(String::as_str) == Some("compressed") {
+            let compressed = graph.compress(|valve| valve.flow > 0 || valve.name == "AA");
+            println!("{}", compressed.to_dot(&graph));
+        } else {
+            println!("{}", graph.to_dot());
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("brute") {
+        // the original search, to cross-check the bitmask solver
+        let problem = ProblemStatement::from_str(&input);
+        println!("Brute force part 1: {}", problem.solve_part1());
+        println!("  Calculation for part 2 will take a while...");
+        println!("Brute force part 2: {}", problem.solve_part2());
+        return;
+    }
+
     println!("Result of part 1: {}", part1(&input));
     println!("Result of part 2: {}", part2(&input));
 }
@@ -13,15 +56,13 @@ fn main() {
 fn part1(input: &str) -> usize {
     let problem = ProblemStatement::from_str(input);
 
-    problem.solve_part1()
+    problem.max_pressure(30)
 }
 
 fn part2(input: &str) -> usize {
     let problem = ProblemStatement::from_str(input);
 
-    println!("  Calculation for part 2 will take a while...");
-
-    problem.solve_part2()
+    problem.max_pressure_with_elephant(26)
 }
 
 // naming is coincidental
@@ -30,24 +71,37 @@ type ValveIndex = HashMap<String, usize>;
 #[derive(Debug, PartialEq)]
 struct Valve {
     id: usize,
+    name: String,
     flow: usize,
     tunnels: Vec<usize>,
 }
 
 impl Valve {
-    fn new(id: usize, flow: usize, tunnels: Vec<usize>) -> Self {
-        Self { id, flow, tunnels }
+    fn new(id: usize, name: &str, flow: usize, tunnels: Vec<usize>) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            flow,
+            tunnels,
+        }
     }
 }
 
-struct ProblemStatement {
+// The tunnel network as scanned, with valve ids in input order.
+#[derive(Debug, PartialEq)]
+struct ValveGraph {
+    valves: Vec<Valve>,
+}
+
+// Shortest walking times between a subset of the valves of a `ValveGraph`.
+// `valves[i]` is the graph id of the valve in row and column `i`.
+#[derive(Debug, PartialEq)]
+struct CompressedGraph {
+    valves: Vec<usize>,
     distances: Vec<Vec<usize>>,
-    flows: Vec<usize>,
-    closed_valves: Vec<usize>,
-    starting_valve: usize,
 }
 
-impl ProblemStatement {
+impl ValveGraph {
     fn from_str(input: &str) -> Self {
         // First, get indices
         let indices: ValveIndex = input
@@ -63,19 +117,14 @@ impl ProblemStatement {
             })
             .collect();
 
-        let valves: Vec<Valve> = input
+        let valves = input
             .trim()
             .lines()
             .enumerate()
-            .map(|(id, line)| ProblemStatement::parse_valve(line, id, &indices))
+            .map(|(id, line)| ValveGraph::parse_valve(line, id, &indices))
             .collect();
 
-        ProblemStatement {
-            distances: ProblemStatement::get_distance_matrix(&valves),
-            flows: valves.iter().map(|valve| valve.flow).collect(),
-            closed_valves: ProblemStatement::get_closed_valves(&valves),
-            starting_valve: indices.get("AA").unwrap().to_owned(),
-        }
+        ValveGraph { valves }
     }
 
     fn parse_valve(line: &str, id: usize, indices: &ValveIndex) -> Valve {
@@ -85,8 +134,9 @@ impl ProblemStatement {
             line,
             "Valve {str} has flow rate={usize}; tunnels lead to valves {str}"
         ) {
-            Ok((_name, flow_rate, tunnels)) => Valve::new(
+            Ok((name, flow_rate, tunnels)) => Valve::new(
                 id,
+                name,
                 flow_rate,
                 tunnels
                     .split(", ")
@@ -97,17 +147,269 @@ impl ProblemStatement {
                 line,
                 "Valve {str} has flow rate={usize}; tunnel leads to valve {str}"
             ) {
-                Ok((_name, flow_rate, tunnel)) => {
-                    Valve::new(id, flow_rate, vec![indices.get(tunnel).unwrap().to_owned()])
-                }
+                Ok((name, flow_rate, tunnel)) => Valve::new(
+                    id,
+                    name,
+                    flow_rate,
+                    vec![indices.get(tunnel).unwrap().to_owned()],
+                ),
                 Err(_) => match sscanf!(line, "Valve {str} has flow rate={usize}") {
-                    Ok((_name, flow_rate)) => Valve::new(id, flow_rate, vec![]),
+                    Ok((name, flow_rate)) => Valve::new(id, name, flow_rate, vec![]),
                     Err(_) => panic!("Invalid input: {}", line),
                 },
             },
         }
     }
 
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.valves.iter().position(|valve| valve.name == name)
+    }
+
+    // Minutes needed to walk between every pair of valves, found with
+    // Floyd–Warshall. A valve is 0 minutes from itself, and `usize::MAX`
+    // marks valves that cannot be reached.
+    fn distances(&self) -> Vec<Vec<usize>> {
+        let n = self.valves.len();
+        let mut distances = vec![vec![usize::MAX; n]; n];
+
+        for valve in &self.valves {
+            distances[valve.id][valve.id] = 0;
+            for &tunnel in &valve.tunnels {
+                distances[valve.id][tunnel] = distances[valve.id][tunnel].min(1);
+            }
+        }
+
+        for k in 0..n {
+            for i in 0..n {
+                if distances[i][k] == usize::MAX {
+                    continue;
+                }
+                for j in 0..n {
+                    let through_k = distances[i][k].saturating_add(distances[k][j]);
+                    if through_k < distances[i][j] {
+                        distances[i][j] = through_k;
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    // Keeps only the valves matching `keep`, connected by the shortest walk
+    // through the rest of the network.
+    fn compress(&self, keep: impl Fn(&Valve) -> bool) -> CompressedGraph {
+        let all = self.distances();
+        let valves: Vec<usize> = self
+            .valves
+            .iter()
+            .filter(|valve| keep(valve))
+            .map(|valve| valve.id)
+            .collect();
+        let distances = valves
+            .iter()
+            .map(|&from| valves.iter().map(|&to| all[from][to]).collect())
+            .collect();
+
+        CompressedGraph { valves, distances }
+    }
+
+    fn dot_node(valve: &Valve) -> String {
+        if valve.flow > 0 {
+            format!(
+                "  {} [label=\"{}\\n{}\", style=filled, fillcolor=lightblue];",
+                valve.name, valve.name, valve.flow
+            )
+        } else {
+            format!("  {} [label=\"{}\"];", valve.name, valve.name)
+        }
+    }
+
+    // The tunnel network in Graphviz DOT format. Valves with a flow rate are
+    // filled and labelled with it.
+    fn to_dot(&self) -> String {
+        let mut lines = vec!["graph valves {".to_string()];
+        lines.extend(self.valves.iter().map(ValveGraph::dot_node));
+
+        for valve in &self.valves {
+            for &tunnel in &valve.tunnels {
+                // tunnels are listed from both ends, draw each once
+                let back = self.valves[tunnel].tunnels.contains(&valve.id);
+                if !back || valve.id < tunnel {
+                    lines.push(format!("  {} -- {};", valve.name, self.valves[tunnel].name));
+                }
+            }
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+impl CompressedGraph {
+    // The compressed network in DOT format, with walking times as edge
+    // labels. Edges whose shortest walk passes another kept valve are left
+    // out, as they follow from the drawn ones.
+    fn to_dot(&self, graph: &ValveGraph) -> String {
+        let mut lines = vec!["graph valves {".to_string()];
+        lines.extend(
+            self.valves
+                .iter()
+                .map(|&id| ValveGraph::dot_node(&graph.valves[id])),
+        );
+
+        let n = self.valves.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                let distance = self.distances[i][j];
+                let direct = (0..n)
+                    .filter(|&k| k != i && k != j)
+                    .all(|k| self.distances[i][k].saturating_add(self.distances[k][j]) > distance);
+                if distance != usize::MAX && direct {
+                    lines.push(format!(
+                        "  {} -- {} [label={}];",
+                        graph.valves[self.valves[i]].name,
+                        graph.valves[self.valves[j]].name,
+                        distance
+                    ));
+                }
+            }
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+struct ProblemStatement {
+    names: Vec<String>,
+    // Walking minutes between valves, without opening them.
+    distances: Vec<Vec<usize>>,
+    // Minutes it takes to open a valve once there.
+    opening_cost: usize,
+    flows: Vec<usize>,
+    closed_valves: Vec<usize>,
+    starting_valve: usize,
+}
+
+// Someone walking the tunnels, starting at valve index `start` with `time`
+// minutes to spend.
+#[derive(Debug, Clone, PartialEq)]
+struct Agent {
+    start: usize,
+    time: usize,
+}
+
+impl Agent {
+    fn new(start: usize, time: usize) -> Self {
+        Self { start, time }
+    }
+}
+
+// A point in an agent's walk: (minutes left, position, opened set).
+type State = (usize, usize, usize);
+
+// The states of an agent with the same minutes left, mapping (position,
+// opened set) to the most pressure released so far and the previous state.
+type Layer = HashMap<(usize, usize), (usize, Option<State>)>;
+
+// The valves one agent opens, in order, and the pressure they release.
+#[derive(Debug, Clone, PartialEq)]
+struct Route {
+    pressure: usize,
+    valves: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Move { to: usize },
+    Open { valve: usize, released: usize },
+}
+
+// An action and the minute, counted from the agent's start, at which it is
+// finished.
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    minute: usize,
+    action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Plan {
+    pressure: usize,
+    agents: Vec<Agent>,
+    timelines: Vec<Vec<Step>>,
+}
+
+impl Plan {
+    fn describe(&self, names: &[String]) -> String {
+        let mut lines = vec![format!("Total pressure released: {}", self.pressure)];
+
+        for (i, (agent, timeline)) in self.agents.iter().zip(&self.timelines).enumerate() {
+            lines.push(format!(
+                "Agent {} starts at {} with {} minutes",
+                i + 1,
+                names[agent.start],
+                agent.time
+            ));
+            for step in timeline {
+                let action = match step.action {
+                    Action::Move { to } => format!("move to {}", names[to]),
+                    Action::Open { valve, released } => {
+                        format!("open {}, releasing {}", names[valve], released)
+                    }
+                };
+                lines.push(format!("  minute {:2}: {}", step.minute, action));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl ProblemStatement {
+    fn from_str(input: &str) -> Self {
+        ProblemStatement::from_graph(&ValveGraph::from_str(input), &["AA"])
+    }
+
+    // The puzzle on the graph compressed to the valves with a flow rate and
+    // the `starts` agents may set out from, so the solvers never step through
+    // the others. The first start is the `starting_valve` of parts 1 and 2.
+    fn from_graph(graph: &ValveGraph, starts: &[&str]) -> Self {
+        let starts: Vec<usize> = starts
+            .iter()
+            .map(|&name| {
+                graph
+                    .index_of(name)
+                    .unwrap_or_else(|| panic!("Unknown valve {}", name))
+            })
+            .collect();
+        let compressed = graph.compress(|valve| valve.flow > 0 || starts.contains(&valve.id));
+        let valves: Vec<&Valve> = compressed
+            .valves
+            .iter()
+            .map(|&id| &graph.valves[id])
+            .collect();
+        let flows: Vec<usize> = valves.iter().map(|valve| valve.flow).collect();
+
+        ProblemStatement {
+            names: valves.iter().map(|valve| valve.name.clone()).collect(),
+            distances: compressed.distances,
+            opening_cost: 1,
+            closed_valves: ProblemStatement::get_closed_valves(&flows),
+            flows,
+            starting_valve: compressed
+                .valves
+                .iter()
+                .position(|&id| id == starts[0])
+                .unwrap(),
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|valve| valve == name)
+    }
+
     fn solve_part1(&self) -> usize {
         let time_left = 30;
 
@@ -132,7 +434,8 @@ impl ProblemStatement {
         remaining_valves.reverse();
 
         for (i, valve) in remaining_valves.iter().enumerate() {
-            let distance_to_valve = self.distances[current_valve][*valve];
+            let distance_to_valve =
+                self.distances[current_valve][*valve].saturating_add(self.opening_cost);
             if distance_to_valve > time_left {
                 continue;
             }
@@ -155,41 +458,11 @@ impl ProblemStatement {
         best_solution
     }
 
-    fn get_distance_matrix(valves: &[Valve]) -> Vec<Vec<usize>> {
-        let mut matrix = vec![vec![usize::MAX; valves.len()]; valves.len()];
-
-        // naive algorithm:
-        // For each index, find the neighbor indices, and increase the distance by 1 each time.
-        // Stop when no more indices are found. Skip indices with distance != usize::MAX
-        //
-        // I know there's better algorithms, but this is good enough for the problem at hand, and
-        // that's good enough for me
-
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..valves.len() {
-            let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
-
-            queue.push_back((i, 1));
-            matrix[i][i] = 1;
-
-            while let Some((index, distance)) = queue.pop_front() {
-                for j in &valves[index].tunnels {
-                    if matrix[i][*j] == usize::MAX {
-                        matrix[i][*j] = distance + 1;
-                        queue.push_back((*j, distance + 1));
-                    }
-                }
-            }
-        }
-
-        matrix
-    }
-
-    fn get_closed_valves(valves: &[Valve]) -> Vec<usize> {
-        valves
+    fn get_closed_valves(flows: &[usize]) -> Vec<usize> {
+        flows
             .iter()
             .enumerate()
-            .filter_map(|(i, valve)| if valve.flow > 0 { Some(i) } else { None })
+            .filter_map(|(i, &flow)| if flow > 0 { Some(i) } else { None })
             .collect()
     }
 
@@ -225,6 +498,207 @@ impl ProblemStatement {
 
         max_flow
     }
+
+    // The most pressure released within `time` minutes for every set of
+    // opened valves, indexed by a bitmask over `closed_valves`. Sets that
+    // cannot be opened in time stay at 0.
+    fn best_pressure_per_opened_set(&self, time: usize) -> Vec<usize> {
+        let agent = Agent::new(self.starting_valve, time);
+
+        self.best_routes(&agent)
+            .into_iter()
+            .map(|route| route.map_or(0, |route| route.pressure))
+            .collect()
+    }
+
+    // The best route of a single agent for every set of opened valves,
+    // indexed like `best_pressure_per_opened_set`. Sets the agent cannot
+    // open within its time budget are `None`.
+    //
+    // A dynamic program over (position, opened set, minutes left): states
+    // are expanded from the most minutes left down, and each one only keeps
+    // the most pressure released on the way there and the state it came from.
+    fn best_routes(&self, agent: &Agent) -> Vec<Option<Route>> {
+        let mut states: Vec<Layer> = vec![HashMap::new(); agent.time + 1];
+        states[agent.time].insert((agent.start, 0), (0, None));
+
+        // the most pressure for every opened set, and the state it ends in
+        let mut best: Vec<Option<(usize, State)>> = vec![None; 1 << self.closed_valves.len()];
+
+        for time_left in (0..=agent.time).rev() {
+            // a valve next to the agent that opens instantly leads to a state
+            // with as many minutes left, so it is expanded in this same round
+            let mut pending: Vec<(usize, usize)> = states[time_left].keys().copied().collect();
+
+            while let Some((position, opened)) = pending.pop() {
+                let pressure = states[time_left][&(position, opened)].0;
+                if best[opened].is_none_or(|(best, _)| best < pressure) {
+                    best[opened] = Some((pressure, (time_left, position, opened)));
+                }
+
+                for (bit, &valve) in self.closed_valves.iter().enumerate() {
+                    let minutes = self.distances[position][valve].saturating_add(self.opening_cost);
+                    if opened & 1 << bit != 0 || minutes >= time_left {
+                        continue;
+                    }
+
+                    let new_time_left = time_left - minutes;
+                    let new_pressure = pressure + new_time_left * self.flows[valve];
+                    let next = (valve, opened | 1 << bit);
+
+                    let layer = &mut states[new_time_left];
+                    if layer
+                        .get(&next)
+                        .is_none_or(|&(known, _)| known < new_pressure)
+                    {
+                        layer.insert(next, (new_pressure, Some((time_left, position, opened))));
+                        if minutes == 0 {
+                            pending.push(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        best.into_iter()
+            .map(|best| {
+                let (pressure, mut state) = best?;
+                let mut valves = Vec::new();
+
+                // walk back to the start, which has no previous state
+                while let Some(previous) = states[state.0][&(state.1, state.2)].1 {
+                    valves.push(state.1);
+                    state = previous;
+                }
+                valves.reverse();
+
+                Some(Route { pressure, valves })
+            })
+            .collect()
+    }
+
+    // The best plan for any number of agents opening disjoint sets of
+    // valves, each with its own start valve and time budget. Opening a valve
+    // takes `opening_cost` minutes. Agents are added one at a time, pairing
+    // every set with each of its subsets: O(agents · 3^valves).
+    fn plan(&self, agents: &[Agent]) -> Plan {
+        let sets = 1 << self.closed_valves.len();
+        let routes: Vec<Vec<Option<Route>>> =
+            agents.iter().map(|agent| self.best_routes(agent)).collect();
+
+        // best[set] is the most pressure the agents so far release opening
+        // exactly `set`; choices[i][set] is the part opened by agent i
+        let mut best: Vec<Option<usize>> = vec![None; sets];
+        best[0] = Some(0);
+        let mut choices = Vec::new();
+
+        for agent_routes in &routes {
+            let mut next = vec![None; sets];
+            let mut choice = vec![0; sets];
+
+            for set in 0..sets {
+                // walk through all subsets of `set`, including the empty one
+                let mut own = set;
+                loop {
+                    let others = best[set ^ own];
+                    if let (Some(others), Some(route)) = (others, &agent_routes[own]) {
+                        let pressure = others + route.pressure;
+                        if next[set].is_none_or(|best| best < pressure) {
+                            next[set] = Some(pressure);
+                            choice[set] = own;
+                        }
+                    }
+                    if own == 0 {
+                        break;
+                    }
+                    own = (own - 1) & set;
+                }
+            }
+
+            best = next;
+            choices.push(choice);
+        }
+
+        let (mut set, pressure) = best
+            .iter()
+            .enumerate()
+            .filter_map(|(set, pressure)| pressure.map(|pressure| (set, pressure)))
+            .max_by_key(|&(_, pressure)| pressure)
+            .unwrap();
+
+        let mut timelines = vec![Vec::new(); agents.len()];
+        for (i, agent) in agents.iter().enumerate().rev() {
+            let own = choices[i][set];
+            let route = routes[i][own].as_ref().unwrap();
+            timelines[i] = self.timeline(agent, route);
+            set ^= own;
+        }
+
+        Plan {
+            pressure,
+            agents: agents.to_vec(),
+            timelines,
+        }
+    }
+
+    fn timeline(&self, agent: &Agent, route: &Route) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let mut minute = 0;
+        let mut position = agent.start;
+
+        for &valve in &route.valves {
+            let travel = self.distances[position][valve];
+            if travel > 0 {
+                minute += travel;
+                steps.push(Step {
+                    minute,
+                    action: Action::Move { to: valve },
+                });
+            }
+
+            minute += self.opening_cost;
+            steps.push(Step {
+                minute,
+                action: Action::Open {
+                    valve,
+                    released: (agent.time - minute) * self.flows[valve],
+                },
+            });
+            position = valve;
+        }
+
+        steps
+    }
+
+    fn max_pressure(&self, time: usize) -> usize {
+        self.best_pressure_per_opened_set(time)
+            .into_iter()
+            .max()
+            .unwrap()
+    }
+
+    // Two agents open disjoint sets of valves. After spreading every set's
+    // best pressure to its supersets, each set only has to be paired with
+    // the best subset of its complement.
+    fn max_pressure_with_elephant(&self, time: usize) -> usize {
+        let best = self.best_pressure_per_opened_set(time);
+        let all = best.len() - 1;
+
+        let mut best_within = best.clone();
+        for bit in 0..self.closed_valves.len() {
+            for set in 0..best_within.len() {
+                if set & 1 << bit != 0 {
+                    best_within[set] = best_within[set].max(best_within[set ^ 1 << bit]);
+                }
+            }
+        }
+
+        best.iter()
+            .enumerate()
+            .map(|(set, pressure)| pressure + best_within[all ^ set])
+            .max()
+            .unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -237,10 +711,12 @@ mod tests {
 
         assert_eq!(problem.flows[0], 0);
 
-        assert_eq!(problem.distances[0], [1, 2, 3, 2, 3, 4, 5, 6, 2, 3]);
-        assert_eq!(problem.distances[1], [2, 1, 2, 3, 4, 5, 6, 7, 3, 4]);
+        // only AA and the valves with a flow rate are left
+        assert_eq!(problem.names, ["AA", "BB", "CC", "DD", "EE", "HH", "JJ"]);
+        assert_eq!(problem.distances[0], [0, 1, 2, 1, 2, 5, 2]);
+        assert_eq!(problem.distances[1], [1, 0, 1, 2, 3, 6, 3]);
 
-        assert_eq!(problem.closed_valves, [1, 2, 3, 4, 7, 9]);
+        assert_eq!(problem.closed_valves, [1, 2, 3, 4, 5, 6]);
 
         assert_eq!(
             ProblemStatement::from_str("Valve AA has flow rate=0").solve_part1(),
@@ -261,6 +737,226 @@ mod tests {
         assert_eq!(problem.solve_part2(), 1707);
     }
 
+    #[test]
+    fn test_bitmask() {
+        let problem = ProblemStatement::from_str(INPUT);
+
+        assert_eq!(problem.max_pressure(30), 1651);
+        assert_eq!(problem.max_pressure_with_elephant(26), 1707);
+
+        let best = problem.best_pressure_per_opened_set(30);
+        assert_eq!(best.len(), 1 << 6);
+        assert_eq!(best[0], 0);
+        // BB alone: two minutes to walk there and open it, then 28 minutes of 13
+        assert_eq!(best[1], 28 * 13);
+
+        for time in [0, 1, 5, 12, 20] {
+            assert_eq!(
+                problem.max_pressure(time),
+                problem.partial_solve_part1(problem.starting_valve, time, &problem.closed_valves)
+            );
+        }
+
+        let single = ProblemStatement::from_str("Valve AA has flow rate=1");
+        assert_eq!(single.max_pressure(30), 29);
+        assert_eq!(single.max_pressure_with_elephant(26), 25);
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut problem = ProblemStatement::from_str(INPUT);
+        let start = problem.starting_valve;
+        let index = |name: &str| problem.index_of(name).unwrap();
+
+        let plan = problem.plan(&[Agent::new(start, 30)]);
+        assert_eq!(plan.pressure, 1651);
+        let opened: Vec<&str> = plan.timelines[0]
+            .iter()
+            .filter_map(|step| match step.action {
+                Action::Open { valve, .. } => Some(problem.names[valve].as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(opened, ["DD", "BB", "JJ", "HH", "EE", "CC"]);
+        assert_eq!(
+            plan.timelines[0][..4],
+            [
+                Step {
+                    minute: 1,
+                    action: Action::Move { to: index("DD") }
+                },
+                Step {
+                    minute: 2,
+                    action: Action::Open {
+                        valve: index("DD"),
+                        released: 28 * 20
+                    }
+                },
+                Step {
+                    minute: 4,
+                    action: Action::Move { to: index("BB") }
+                },
+                Step {
+                    minute: 5,
+                    action: Action::Open {
+                        valve: index("BB"),
+                        released: 25 * 13
+                    }
+                },
+            ]
+        );
+        assert!(plan
+            .describe(&problem.names)
+            .starts_with("Total pressure released: 1651\nAgent 1 starts at AA with 30 minutes\n  minute  1: move to DD\n  minute  2: open DD, releasing 560"));
+
+        let plan = problem.plan(&[Agent::new(start, 26), Agent::new(start, 26)]);
+        assert_eq!(plan.pressure, 1707);
+        let released: usize = plan
+            .timelines
+            .iter()
+            .flatten()
+            .map(|step| match step.action {
+                Action::Open { released, .. } => released,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(released, 1707);
+
+        // more agents never do worse, and an agent without time adds nothing
+        let three = problem.plan(&vec![Agent::new(start, 26); 3]);
+        assert!(three.pressure >= 1707);
+        let idle = problem.plan(&[Agent::new(start, 30), Agent::new(start, 0)]);
+        assert_eq!(idle.pressure, 1651);
+        assert!(idle.timelines[1].is_empty());
+
+        // starting next to JJ and opening valves for free
+        let jj = index("JJ");
+        problem.opening_cost = 0;
+        let plan = problem.plan(&[Agent::new(jj, 2)]);
+        assert_eq!(plan.pressure, 2 * 21);
+        let mut problem = ProblemStatement::from_graph(&ValveGraph::from_str(INPUT), &["II"]);
+        problem.opening_cost = 2;
+        let start = problem.starting_valve;
+        assert_eq!(problem.names[start], "II");
+        let plan = problem.plan(&[Agent::new(start, 3)]);
+        assert_eq!(plan.pressure, 0);
+        let plan = problem.plan(&[Agent::new(start, 4)]);
+        assert_eq!(plan.pressure, 21);
+
+        // agents setting out from different valves without a flow rate
+        let graph = ValveGraph::from_str(INPUT);
+        let problem = ProblemStatement::from_graph(&graph, &["FF", "II"]);
+        let (ff, ii) = (
+            problem.index_of("FF").unwrap(),
+            problem.index_of("II").unwrap(),
+        );
+        assert_eq!(problem.names[problem.starting_valve], "FF");
+        assert_eq!(problem.index_of("GG"), None);
+
+        let plan = problem.plan(&[Agent::new(ff, 26), Agent::new(ii, 26)]);
+        let description = plan.describe(&problem.names);
+        assert!(description.contains("Agent 1 starts at FF with 26 minutes"));
+        assert!(description.contains("Agent 2 starts at II with 26 minutes"));
+        // FF is next to EE and two steps from HH, II is next to JJ and AA
+        assert_eq!(
+            plan.timelines[1][..2],
+            [
+                Step {
+                    minute: 1,
+                    action: Action::Move {
+                        to: problem.index_of("JJ").unwrap()
+                    }
+                },
+                Step {
+                    minute: 2,
+                    action: Action::Open {
+                        valve: problem.index_of("JJ").unwrap(),
+                        released: 24 * 21
+                    }
+                },
+            ]
+        );
+        let alone = ProblemStatement::from_graph(&graph, &["II"]);
+        assert!(plan.pressure > alone.plan(&[Agent::new(alone.starting_valve, 26)]).pressure);
+    }
+
+    #[test]
+    fn test_graph() {
+        let graph = ValveGraph::from_str(INPUT);
+
+        assert_eq!(graph.valves.len(), 10);
+        assert_eq!(graph.valves[7], Valve::new(7, "HH", 22, vec![6]));
+        assert_eq!(graph.index_of("JJ"), Some(9));
+        assert_eq!(graph.index_of("ZZ"), None);
+    }
+
+    #[test]
+    fn test_distances() {
+        let distances = ValveGraph::from_str(INPUT).distances();
+
+        for (i, row) in distances.iter().enumerate() {
+            assert_eq!(row[i], 0);
+            for (j, &distance) in row.iter().enumerate() {
+                assert_eq!(distance, distances[j][i]);
+            }
+        }
+        // HH - GG - FF - EE - DD - AA - II - JJ
+        assert_eq!(distances[7][9], 7);
+
+        let unreachable = ValveGraph::from_str(
+            "Valve AA has flow rate=0; tunnel leads to valve AA\nValve BB has flow rate=5",
+        );
+        assert_eq!(unreachable.distances()[0][1], usize::MAX);
+        assert_eq!(
+            ProblemStatement::from_graph(&unreachable, &["AA"]).max_pressure(30),
+            0
+        );
+    }
+
+    #[test]
+    fn test_compress() {
+        let graph = ValveGraph::from_str(INPUT);
+        let compressed = graph.compress(|valve| valve.flow > 0 || valve.name == "AA");
+
+        assert_eq!(compressed.valves, [0, 1, 2, 3, 4, 7, 9]);
+        assert_eq!(compressed.distances[0], [0, 1, 2, 1, 2, 5, 2]);
+    }
+
+    #[test]
+    fn test_dot() {
+        let graph = ValveGraph::from_str(INPUT);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("graph valves {\n  AA [label=\"AA\"];\n  BB [label=\"BB\\n13\", style=filled, fillcolor=lightblue];"));
+        assert_eq!(dot.matches(" -- ").count(), 10);
+        assert!(dot.contains("  AA -- DD;") && !dot.contains("  DD -- AA;"));
+
+        let compressed = graph.compress(|valve| valve.flow > 0 || valve.name == "AA");
+        let dot = compressed.to_dot(&graph);
+        assert!(dot.contains("  EE -- HH [label=3];"));
+        assert!(dot.contains("  AA -- JJ [label=2];"));
+        // BB to EE passes through DD, so it is implied by BB -- .. -- DD -- EE
+        assert!(!dot.contains("BB -- EE"));
+    }
+
+    #[test]
+    fn test_opening_cost() {
+        let mut problem = ProblemStatement::from_str(INPUT);
+        assert_eq!(problem.opening_cost, 1);
+        assert_eq!(problem.max_pressure(30), 1651);
+
+        // the solvers and the plans all read the same cost
+        problem.opening_cost = 0;
+        let free = problem.max_pressure(30);
+        assert!(free > 1651);
+        assert_eq!(
+            free,
+            problem.partial_solve_part1(problem.starting_valve, 30, &problem.closed_valves)
+        );
+        let plan = problem.plan(&[Agent::new(problem.starting_valve, 30)]);
+        assert_eq!(plan.pressure, free);
+    }
+
     const INPUT: &str = "Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
 Valve BB has flow rate=13; tunnels lead to valves CC, AA
 Valve CC has flow rate=2; tunnels lead to valves DD, BB